@@ -2,15 +2,60 @@ import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
-export interface BlockInfo { 'height' : number, 'hash' : string }
+export interface BlockGap { 'to' : number, 'from' : number }
+export interface BlockInfo {
+  'height' : number,
+  'hash' : string,
+  'confirmed_txids' : Array<string>,
+  'timestamp' : bigint,
+}
+export interface BlocksPage {
+  'next' : [] | [number],
+  'blocks' : Array<BlockInfo>,
+}
 export interface BorrowOffer {
-  'pool_utxo' : Utxo,
+  'pool_utxos' : Array<Utxo>,
   'nonce' : bigint,
   'input_runes' : CoinBalance,
   'output_btc' : CoinBalance,
 }
 export interface CoinBalance { 'id' : string, 'value' : bigint }
-export interface DepositOffer { 'pool_utxo' : [] | [Utxo], 'nonce' : bigint }
+export interface CoinMeta {
+  'id' : string,
+  'min_amount' : bigint,
+  'symbol' : string,
+}
+export interface Config {
+  'network' : Network,
+  'orchestrator' : [] | [Principal],
+}
+export interface ConsolidateOffer {
+  'pool_utxos' : Array<Utxo>,
+  'nonce' : bigint,
+  'output_btc' : CoinBalance,
+}
+export interface DepositOffer { 'pool_utxos' : Array<Utxo>, 'nonce' : bigint }
+export interface Event {
+  'kind' : EventKind,
+  'seq' : bigint,
+  'block_height' : [] | [number],
+  'pool_address' : string,
+  'inputs' : Array<CoinBalance>,
+  'timestamp' : bigint,
+  'txid' : string,
+  'outputs' : Array<CoinBalance>,
+}
+export type EventKind = { 'Drop' : null } |
+  { 'Withdraw' : null } |
+  { 'Deposit' : null } |
+  { 'Repay' : null } |
+  { 'Reorg' : null } |
+  { 'Confirm' : null } |
+  { 'Rollback' : null } |
+  { 'Finalize' : null } |
+  { 'Borrow' : null } |
+  { 'Liquidate' : null } |
+  { 'Consolidate' : null };
 export type ExchangeError = { 'InvalidSignPsbtArgs' : string } |
   { 'Overflow' : null } |
   { 'PoolStateExpired' : bigint } |
@@ -18,7 +63,13 @@ export type ExchangeError = { 'InvalidSignPsbtArgs' : string } |
   { 'InvalidPool' : null } |
   { 'InvalidTxid' : null } |
   { 'EmptyPool' : null } |
-  { 'InvalidState' : string };
+  { 'InvalidState' : string } |
+  { 'NoOutstandingLoan' : null } |
+  { 'PriceUnavailable' : null } |
+  { 'PriceStale' : bigint } |
+  { 'LoanHealthy' : null } |
+  { 'NoLiquidityPosition' : null } |
+  { 'Paused' : string };
 export interface ExecuteTxArgs {
   'zero_confirmed_tx_queue_length' : number,
   'txid' : string,
@@ -31,6 +82,17 @@ export interface GetMinimalTxValueArgs {
   'pool_address' : string,
 }
 export interface GetPoolInfoArgs { 'pool_address' : string }
+export interface HttpRequest {
+  'url' : string,
+  'method' : string,
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+}
+export interface HttpResponse {
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+  'status_code' : number,
+}
 export interface InputCoin { 'coin' : CoinBalance, 'from' : string }
 export interface Intention {
   'input_coins' : Array<InputCoin>,
@@ -48,6 +110,33 @@ export interface IntentionSet {
   'initiator_address' : string,
   'intentions' : Array<Intention>,
 }
+export interface LiquidateOffer {
+  'pool_utxos' : Array<Utxo>,
+  'nonce' : bigint,
+  'input_btc' : CoinBalance,
+  'output_runes' : CoinBalance,
+}
+export interface LoanInfo {
+  'collateral' : CoinBalance,
+  'debt' : CoinBalance,
+  'pool_address' : string,
+  'borrower' : string,
+}
+export interface LpPositionInfo {
+  'total_shares' : bigint,
+  'value' : CoinBalance,
+  'owner' : string,
+  'shares' : bigint,
+  'pool_address' : string,
+}
+export interface MinTxValueCurve {
+  'cap' : bigint,
+  'base' : bigint,
+  'step_bps' : bigint,
+}
+export type Network = { 'Mainnet' : null } |
+  { 'Regtest' : null } |
+  { 'Testnet4' : null };
 export interface NewBlockInfo {
   'block_hash' : string,
   'confirmed_txids' : Array<string>,
@@ -55,6 +144,12 @@ export interface NewBlockInfo {
   'block_height' : number,
 }
 export interface OutputCoin { 'to' : string, 'coin' : CoinBalance }
+export interface PauseFlags {
+  'repay' : boolean,
+  'borrow' : boolean,
+  'deposit' : boolean,
+  'liquidate' : boolean,
+}
 export interface PoolBasic { 'name' : string, 'address' : string }
 export interface PoolInfo {
   'key' : string,
@@ -67,10 +162,50 @@ export interface PoolInfo {
   'nonce' : bigint,
   'utxos' : Array<Utxo>,
 }
+export interface PoolParams {
+  'liquidation_bonus_bps' : bigint,
+  'liquidation_threshold_bps' : bigint,
+  'ltv_bps' : bigint,
+  'min_tx_value' : MinTxValueCurve,
+  'rate_model' : RateModel,
+}
+export interface PoolRates {
+  'utilization_bps' : bigint,
+  'total_borrowed' : bigint,
+  'borrow_rate_bps' : bigint,
+  'supply_rate_bps' : bigint,
+}
+export interface Price { 'updated_at' : bigint, 'value' : bigint }
+export interface RateModel {
+  'base_rate_bps' : bigint,
+  'slope1_bps' : bigint,
+  'optimal_utilization_bps' : bigint,
+  'slope2_bps' : bigint,
+}
+export interface RepayOffer {
+  'pool_utxos' : Array<Utxo>,
+  'nonce' : bigint,
+  'input_btc' : CoinBalance,
+  'output_runes' : CoinBalance,
+}
 export type Result = { 'Ok' : [bigint, bigint] } |
   { 'Err' : string };
 export type Result_1 = { 'Ok' : string } |
   { 'Err' : string };
+export type Result_10 = { 'Ok' : LiquidateOffer } |
+  { 'Err' : ExchangeError };
+export type Result_11 = { 'Ok' : WithdrawOffer } |
+  { 'Err' : ExchangeError };
+export type Result_12 = { 'Ok' : LpPositionInfo } |
+  { 'Err' : ExchangeError };
+export type Result_13 = { 'Ok' : Array<BlockGap> } |
+  { 'Err' : string };
+export type Result_14 = { 'Ok' : ConsolidateOffer } |
+  { 'Err' : ExchangeError };
+export type Result_15 = { 'Ok' : BlocksPage } |
+  { 'Err' : string };
+export type Result_16 = { 'Ok' : TxRecordsPage } |
+  { 'Err' : string };
 export type Result_2 = { 'Ok' : null } |
   { 'Err' : string };
 export type Result_3 = { 'Ok' : BorrowOffer } |
@@ -81,33 +216,95 @@ export type Result_5 = { 'Ok' : Array<BlockInfo> } |
   { 'Err' : string };
 export type Result_6 = { 'Ok' : Array<TxRecordInfo> } |
   { 'Err' : string };
+export type Result_7 = { 'Ok' : Array<LoanInfo> } |
+  { 'Err' : ExchangeError };
+export type Result_8 = { 'Ok' : RepayOffer } |
+  { 'Err' : ExchangeError };
+export type Result_9 = { 'Ok' : PoolRates } |
+  { 'Err' : ExchangeError };
+export type Role = { 'Admin' : null } |
+  { 'RiskManager' : null } |
+  { 'Pauser' : null } |
+  { 'OracleFeeder' : null };
 export interface RollbackTxArgs { 'txid' : string }
+export interface TxRecordFilter {
+  'confirmed' : [] | [boolean],
+  'pool_address' : [] | [string],
+}
 export interface TxRecordInfo {
+  'states' : Array<TxStateInfo>,
   'records' : Array<string>,
   'txid' : string,
   'confirmed' : boolean,
 }
+export interface TxRecordKey { 'txid' : string, 'confirmed' : boolean }
+export interface TxRecordsPage {
+  'next' : [] | [TxRecordKey],
+  'records' : Array<TxRecordInfo>,
+}
+export interface TxStateInfo {
+  'rune_supply' : [] | [bigint],
+  'btc_supply' : [] | [bigint],
+  'state_index' : [] | [bigint],
+  'pool_address' : string,
+  'nonce' : [] | [bigint],
+  'btc_change' : [] | [bigint],
+  'rune_change' : [] | [bigint],
+}
 export interface Utxo {
   'coins' : Array<CoinBalance>,
   'sats' : bigint,
   'txid' : string,
   'vout' : number,
 }
+export interface WithdrawOffer {
+  'pool_utxos' : Array<Utxo>,
+  'nonce' : bigint,
+  'output_btc' : CoinBalance,
+}
 export interface _SERVICE {
   'blocks_tx_records_count' : ActorMethod<[], Result>,
+  'create_pool' : ActorMethod<[CoinMeta, PoolParams], Result_1>,
   'execute_tx' : ActorMethod<[ExecuteTxArgs], Result_1>,
+  'get_config' : ActorMethod<[], Config>,
+  'get_events' : ActorMethod<[bigint, bigint], Array<Event>>,
+  'get_loan' : ActorMethod<[string], Array<LoanInfo>>,
+  'get_lp_position' : ActorMethod<[string, string], Result_12>,
   'get_minimal_tx_value' : ActorMethod<[GetMinimalTxValueArgs], bigint>,
+  'get_pause_flags' : ActorMethod<[[] | [string]], PauseFlags>,
   'get_pool_info' : ActorMethod<[GetPoolInfoArgs], [] | [PoolInfo]>,
   'get_pool_list' : ActorMethod<[], Array<PoolBasic>>,
-  'init_pool' : ActorMethod<[], Result_2>,
+  'get_price' : ActorMethod<[string], [] | [Price]>,
+  'get_rates' : ActorMethod<[string], Result_9>,
+  'get_roles' : ActorMethod<[Principal], Array<Role>>,
+  'grant_role' : ActorMethod<[Principal, Role], Result_2>,
+  'http_request' : ActorMethod<[HttpRequest], HttpResponse>,
+  'list_loans' : ActorMethod<[string], Result_7>,
   'new_block' : ActorMethod<[NewBlockInfo], Result_2>,
+  'pause_all' : ActorMethod<[PauseFlags], Result_2>,
+  'pause_pool' : ActorMethod<[string, PauseFlags], Result_2>,
   'pre_borrow' : ActorMethod<[string, CoinBalance], Result_3>,
+  'pre_consolidate' : ActorMethod<[string], Result_14>,
   'pre_deposit' : ActorMethod<[string, CoinBalance], Result_4>,
+  'pre_liquidate' : ActorMethod<[string, string, CoinBalance], Result_10>,
+  'pre_repay' : ActorMethod<[string, string, CoinBalance], Result_8>,
+  'pre_withdraw' : ActorMethod<[string, string, CoinBalance], Result_11>,
+  'query_block_gaps' : ActorMethod<[], Result_13>,
   'query_blocks' : ActorMethod<[], Result_5>,
+  'query_blocks_page' : ActorMethod<[[] | [number], [] | [number], bigint], Result_15>,
   'query_tx_records' : ActorMethod<[], Result_6>,
+  'query_tx_records_page' : ActorMethod<[TxRecordFilter, [] | [TxRecordKey], bigint], Result_16>,
   'reset_blocks' : ActorMethod<[], Result_2>,
   'reset_tx_records' : ActorMethod<[], Result_2>,
+  'revoke_role' : ActorMethod<[Principal, Role], Result_2>,
   'rollback_tx' : ActorMethod<[RollbackTxArgs], Result_2>,
+  'set_pool_liquidation_params' : ActorMethod<[string, bigint, bigint], Result_2>,
+  'set_pool_ltv' : ActorMethod<[string, bigint], Result_2>,
+  'set_pool_min_tx_value' : ActorMethod<[string, MinTxValueCurve], Result_2>,
+  'set_pool_rate_model' : ActorMethod<[string, RateModel], Result_2>,
+  'set_price' : ActorMethod<[string, bigint], Result_2>,
+  'unpause_all' : ActorMethod<[PauseFlags], Result_2>,
+  'unpause_pool' : ActorMethod<[string, PauseFlags], Result_2>,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
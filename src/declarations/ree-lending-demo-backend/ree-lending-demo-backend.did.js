@@ -3,6 +3,30 @@ export const idlFactory = ({ IDL }) => {
     'Ok' : IDL.Tuple(IDL.Nat64, IDL.Nat64),
     'Err' : IDL.Text,
   });
+  const CoinMeta = IDL.Record({
+    'id' : IDL.Text,
+    'min_amount' : IDL.Nat,
+    'symbol' : IDL.Text,
+  });
+  const MinTxValueCurve = IDL.Record({
+    'cap' : IDL.Nat64,
+    'base' : IDL.Nat64,
+    'step_bps' : IDL.Nat64,
+  });
+  const RateModel = IDL.Record({
+    'base_rate_bps' : IDL.Nat64,
+    'slope1_bps' : IDL.Nat64,
+    'optimal_utilization_bps' : IDL.Nat64,
+    'slope2_bps' : IDL.Nat64,
+  });
+  const PoolParams = IDL.Record({
+    'liquidation_bonus_bps' : IDL.Nat64,
+    'liquidation_threshold_bps' : IDL.Nat64,
+    'ltv_bps' : IDL.Nat64,
+    'min_tx_value' : MinTxValueCurve,
+    'rate_model' : RateModel,
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text });
   const CoinBalance = IDL.Record({ 'id' : IDL.Text, 'value' : IDL.Nat });
   const InputCoin = IDL.Record({ 'coin' : CoinBalance, 'from' : IDL.Text });
   const OutputCoin = IDL.Record({ 'to' : IDL.Text, 'coin' : CoinBalance });
@@ -35,11 +59,81 @@ export const idlFactory = ({ IDL }) => {
     'intention_index' : IDL.Nat32,
     'psbt_hex' : IDL.Text,
   });
-  const Result_1 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text });
+  const Network = IDL.Variant({
+    'Mainnet' : IDL.Null,
+    'Regtest' : IDL.Null,
+    'Testnet4' : IDL.Null,
+  });
+  const Config = IDL.Record({
+    'network' : Network,
+    'orchestrator' : IDL.Opt(IDL.Principal),
+  });
+  const EventKind = IDL.Variant({
+    'Drop' : IDL.Null,
+    'Withdraw' : IDL.Null,
+    'Deposit' : IDL.Null,
+    'Repay' : IDL.Null,
+    'Reorg' : IDL.Null,
+    'Confirm' : IDL.Null,
+    'Rollback' : IDL.Null,
+    'Finalize' : IDL.Null,
+    'Borrow' : IDL.Null,
+    'Liquidate' : IDL.Null,
+    'Consolidate' : IDL.Null,
+  });
+  const Event = IDL.Record({
+    'kind' : EventKind,
+    'seq' : IDL.Nat64,
+    'block_height' : IDL.Opt(IDL.Nat32),
+    'pool_address' : IDL.Text,
+    'inputs' : IDL.Vec(CoinBalance),
+    'timestamp' : IDL.Nat64,
+    'txid' : IDL.Text,
+    'outputs' : IDL.Vec(CoinBalance),
+  });
+  const LoanInfo = IDL.Record({
+    'collateral' : CoinBalance,
+    'debt' : CoinBalance,
+    'pool_address' : IDL.Text,
+    'borrower' : IDL.Text,
+  });
+  const LpPositionInfo = IDL.Record({
+    'total_shares' : IDL.Nat,
+    'value' : CoinBalance,
+    'owner' : IDL.Text,
+    'shares' : IDL.Nat,
+    'pool_address' : IDL.Text,
+  });
+  const ExchangeError = IDL.Variant({
+    'InvalidSignPsbtArgs' : IDL.Text,
+    'Overflow' : IDL.Null,
+    'PoolStateExpired' : IDL.Nat64,
+    'TooSmallFunds' : IDL.Null,
+    'InvalidPool' : IDL.Null,
+    'InvalidTxid' : IDL.Null,
+    'EmptyPool' : IDL.Null,
+    'InvalidState' : IDL.Text,
+    'NoOutstandingLoan' : IDL.Null,
+    'PriceUnavailable' : IDL.Null,
+    'PriceStale' : IDL.Nat64,
+    'LoanHealthy' : IDL.Null,
+    'NoLiquidityPosition' : IDL.Null,
+    'Paused' : IDL.Text,
+  });
+  const Result_12 = IDL.Variant({
+    'Ok' : LpPositionInfo,
+    'Err' : ExchangeError,
+  });
   const GetMinimalTxValueArgs = IDL.Record({
     'zero_confirmed_tx_queue_length' : IDL.Nat32,
     'pool_address' : IDL.Text,
   });
+  const PauseFlags = IDL.Record({
+    'repay' : IDL.Bool,
+    'borrow' : IDL.Bool,
+    'deposit' : IDL.Bool,
+    'liquidate' : IDL.Bool,
+  });
   const GetPoolInfoArgs = IDL.Record({ 'pool_address' : IDL.Text });
   const PoolInfo = IDL.Record({
     'key' : IDL.Text,
@@ -53,7 +147,36 @@ export const idlFactory = ({ IDL }) => {
     'utxos' : IDL.Vec(Utxo),
   });
   const PoolBasic = IDL.Record({ 'name' : IDL.Text, 'address' : IDL.Text });
+  const Price = IDL.Record({ 'updated_at' : IDL.Nat64, 'value' : IDL.Nat });
+  const PoolRates = IDL.Record({
+    'utilization_bps' : IDL.Nat64,
+    'total_borrowed' : IDL.Nat64,
+    'borrow_rate_bps' : IDL.Nat64,
+    'supply_rate_bps' : IDL.Nat64,
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : PoolRates, 'Err' : ExchangeError });
+  const Role = IDL.Variant({
+    'Admin' : IDL.Null,
+    'RiskManager' : IDL.Null,
+    'Pauser' : IDL.Null,
+    'OracleFeeder' : IDL.Null,
+  });
   const Result_2 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text });
+  const HttpRequest = IDL.Record({
+    'url' : IDL.Text,
+    'method' : IDL.Text,
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+  });
+  const HttpResponse = IDL.Record({
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'status_code' : IDL.Nat16,
+  });
+  const Result_7 = IDL.Variant({
+    'Ok' : IDL.Vec(LoanInfo),
+    'Err' : ExchangeError,
+  });
   const NewBlockInfo = IDL.Record({
     'block_hash' : IDL.Text,
     'confirmed_txids' : IDL.Vec(IDL.Text),
@@ -61,30 +184,77 @@ export const idlFactory = ({ IDL }) => {
     'block_height' : IDL.Nat32,
   });
   const BorrowOffer = IDL.Record({
-    'pool_utxo' : Utxo,
+    'pool_utxos' : IDL.Vec(Utxo),
     'nonce' : IDL.Nat64,
     'input_runes' : CoinBalance,
     'output_btc' : CoinBalance,
   });
-  const ExchangeError = IDL.Variant({
-    'InvalidSignPsbtArgs' : IDL.Text,
-    'Overflow' : IDL.Null,
-    'PoolStateExpired' : IDL.Nat64,
-    'TooSmallFunds' : IDL.Null,
-    'InvalidPool' : IDL.Null,
-    'InvalidTxid' : IDL.Null,
-    'EmptyPool' : IDL.Null,
-    'InvalidState' : IDL.Text,
-  });
   const Result_3 = IDL.Variant({ 'Ok' : BorrowOffer, 'Err' : ExchangeError });
+  const ConsolidateOffer = IDL.Record({
+    'pool_utxos' : IDL.Vec(Utxo),
+    'nonce' : IDL.Nat64,
+    'output_btc' : CoinBalance,
+  });
+  const Result_14 = IDL.Variant({
+    'Ok' : ConsolidateOffer,
+    'Err' : ExchangeError,
+  });
   const DepositOffer = IDL.Record({
-    'pool_utxo' : IDL.Opt(Utxo),
+    'pool_utxos' : IDL.Vec(Utxo),
     'nonce' : IDL.Nat64,
   });
   const Result_4 = IDL.Variant({ 'Ok' : DepositOffer, 'Err' : ExchangeError });
-  const BlockInfo = IDL.Record({ 'height' : IDL.Nat32, 'hash' : IDL.Text });
+  const LiquidateOffer = IDL.Record({
+    'pool_utxos' : IDL.Vec(Utxo),
+    'nonce' : IDL.Nat64,
+    'input_btc' : CoinBalance,
+    'output_runes' : CoinBalance,
+  });
+  const Result_10 = IDL.Variant({
+    'Ok' : LiquidateOffer,
+    'Err' : ExchangeError,
+  });
+  const RepayOffer = IDL.Record({
+    'pool_utxos' : IDL.Vec(Utxo),
+    'nonce' : IDL.Nat64,
+    'input_btc' : CoinBalance,
+    'output_runes' : CoinBalance,
+  });
+  const Result_8 = IDL.Variant({ 'Ok' : RepayOffer, 'Err' : ExchangeError });
+  const WithdrawOffer = IDL.Record({
+    'pool_utxos' : IDL.Vec(Utxo),
+    'nonce' : IDL.Nat64,
+    'output_btc' : CoinBalance,
+  });
+  const Result_11 = IDL.Variant({
+    'Ok' : WithdrawOffer,
+    'Err' : ExchangeError,
+  });
+  const BlockGap = IDL.Record({ 'to' : IDL.Nat32, 'from' : IDL.Nat32 });
+  const Result_13 = IDL.Variant({ 'Ok' : IDL.Vec(BlockGap), 'Err' : IDL.Text });
+  const BlockInfo = IDL.Record({
+    'height' : IDL.Nat32,
+    'hash' : IDL.Text,
+    'confirmed_txids' : IDL.Vec(IDL.Text),
+    'timestamp' : IDL.Nat64,
+  });
   const Result_5 = IDL.Variant({ 'Ok' : IDL.Vec(BlockInfo), 'Err' : IDL.Text });
+  const BlocksPage = IDL.Record({
+    'next' : IDL.Opt(IDL.Nat32),
+    'blocks' : IDL.Vec(BlockInfo),
+  });
+  const Result_15 = IDL.Variant({ 'Ok' : BlocksPage, 'Err' : IDL.Text });
+  const TxStateInfo = IDL.Record({
+    'rune_supply' : IDL.Opt(IDL.Nat),
+    'btc_supply' : IDL.Opt(IDL.Nat64),
+    'state_index' : IDL.Opt(IDL.Nat64),
+    'pool_address' : IDL.Text,
+    'nonce' : IDL.Opt(IDL.Nat64),
+    'btc_change' : IDL.Opt(IDL.Int),
+    'rune_change' : IDL.Opt(IDL.Int),
+  });
   const TxRecordInfo = IDL.Record({
+    'states' : IDL.Vec(TxStateInfo),
     'records' : IDL.Vec(IDL.Text),
     'txid' : IDL.Text,
     'confirmed' : IDL.Bool,
@@ -93,30 +263,111 @@ export const idlFactory = ({ IDL }) => {
     'Ok' : IDL.Vec(TxRecordInfo),
     'Err' : IDL.Text,
   });
+  const TxRecordFilter = IDL.Record({
+    'confirmed' : IDL.Opt(IDL.Bool),
+    'pool_address' : IDL.Opt(IDL.Text),
+  });
+  const TxRecordKey = IDL.Record({ 'txid' : IDL.Text, 'confirmed' : IDL.Bool });
+  const TxRecordsPage = IDL.Record({
+    'next' : IDL.Opt(TxRecordKey),
+    'records' : IDL.Vec(TxRecordInfo),
+  });
+  const Result_16 = IDL.Variant({ 'Ok' : TxRecordsPage, 'Err' : IDL.Text });
   const RollbackTxArgs = IDL.Record({ 'txid' : IDL.Text });
   return IDL.Service({
     'blocks_tx_records_count' : IDL.Func([], [Result], ['query']),
+    'create_pool' : IDL.Func([CoinMeta, PoolParams], [Result_1], []),
     'execute_tx' : IDL.Func([ExecuteTxArgs], [Result_1], []),
+    'get_config' : IDL.Func([], [Config], ['query']),
+    'get_events' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64],
+        [IDL.Vec(Event)],
+        ['query'],
+      ),
+    'get_loan' : IDL.Func([IDL.Text], [IDL.Vec(LoanInfo)], ['query']),
+    'get_lp_position' : IDL.Func([IDL.Text, IDL.Text], [Result_12], ['query']),
     'get_minimal_tx_value' : IDL.Func(
         [GetMinimalTxValueArgs],
         [IDL.Nat64],
         ['query'],
       ),
+    'get_pause_flags' : IDL.Func([IDL.Opt(IDL.Text)], [PauseFlags], ['query']),
     'get_pool_info' : IDL.Func(
         [GetPoolInfoArgs],
         [IDL.Opt(PoolInfo)],
         ['query'],
       ),
     'get_pool_list' : IDL.Func([], [IDL.Vec(PoolBasic)], ['query']),
-    'init_pool' : IDL.Func([], [Result_2], []),
+    'get_price' : IDL.Func([IDL.Text], [IDL.Opt(Price)], ['query']),
+    'get_rates' : IDL.Func([IDL.Text], [Result_9], ['query']),
+    'get_roles' : IDL.Func([IDL.Principal], [IDL.Vec(Role)], ['query']),
+    'grant_role' : IDL.Func([IDL.Principal, Role], [Result_2], []),
+    'http_request' : IDL.Func([HttpRequest], [HttpResponse], ['query']),
+    'list_loans' : IDL.Func([IDL.Text], [Result_7], ['query']),
     'new_block' : IDL.Func([NewBlockInfo], [Result_2], []),
+    'pause_all' : IDL.Func([PauseFlags], [Result_2], []),
+    'pause_pool' : IDL.Func([IDL.Text, PauseFlags], [Result_2], []),
     'pre_borrow' : IDL.Func([IDL.Text, CoinBalance], [Result_3], ['query']),
+    'pre_consolidate' : IDL.Func([IDL.Text], [Result_14], ['query']),
     'pre_deposit' : IDL.Func([IDL.Text, CoinBalance], [Result_4], ['query']),
+    'pre_liquidate' : IDL.Func(
+        [IDL.Text, IDL.Text, CoinBalance],
+        [Result_10],
+        ['query'],
+      ),
+    'pre_repay' : IDL.Func(
+        [IDL.Text, IDL.Text, CoinBalance],
+        [Result_8],
+        ['query'],
+      ),
+    'pre_withdraw' : IDL.Func(
+        [IDL.Text, IDL.Text, CoinBalance],
+        [Result_11],
+        ['query'],
+      ),
+    'query_block_gaps' : IDL.Func([], [Result_13], ['query']),
     'query_blocks' : IDL.Func([], [Result_5], ['query']),
+    'query_blocks_page' : IDL.Func(
+        [IDL.Opt(IDL.Nat32), IDL.Opt(IDL.Nat32), IDL.Nat64],
+        [Result_15],
+        ['query'],
+      ),
     'query_tx_records' : IDL.Func([], [Result_6], ['query']),
+    'query_tx_records_page' : IDL.Func(
+        [TxRecordFilter, IDL.Opt(TxRecordKey), IDL.Nat64],
+        [Result_16],
+        ['query'],
+      ),
     'reset_blocks' : IDL.Func([], [Result_2], []),
     'reset_tx_records' : IDL.Func([], [Result_2], []),
+    'revoke_role' : IDL.Func([IDL.Principal, Role], [Result_2], []),
     'rollback_tx' : IDL.Func([RollbackTxArgs], [Result_2], []),
+    'set_pool_liquidation_params' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Nat64],
+        [Result_2],
+        [],
+      ),
+    'set_pool_ltv' : IDL.Func([IDL.Text, IDL.Nat64], [Result_2], []),
+    'set_pool_min_tx_value' : IDL.Func(
+        [IDL.Text, MinTxValueCurve],
+        [Result_2],
+        [],
+      ),
+    'set_pool_rate_model' : IDL.Func([IDL.Text, RateModel], [Result_2], []),
+    'set_price' : IDL.Func([IDL.Text, IDL.Nat], [Result_2], []),
+    'unpause_all' : IDL.Func([PauseFlags], [Result_2], []),
+    'unpause_pool' : IDL.Func([IDL.Text, PauseFlags], [Result_2], []),
+  });
+};
+export const init = ({ IDL }) => {
+  const Network = IDL.Variant({
+    'Mainnet' : IDL.Null,
+    'Regtest' : IDL.Null,
+    'Testnet4' : IDL.Null,
+  });
+  const Config = IDL.Record({
+    'network' : Network,
+    'orchestrator' : IDL.Opt(IDL.Principal),
   });
+  return [Config];
 };
-export const init = ({ IDL }) => { return []; };
@@ -0,0 +1,97 @@
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+use ic_cdk_macros::query;
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::orchestrator_interfaces::ensure_testnet4_orchestrator;
+use serde::Serialize;
+
+#[derive(Clone, Copy, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize, Default)]
+// Network is the Bitcoin network the exchange operates on
+pub enum Network {
+    Mainnet,
+    #[default]
+    Testnet4,
+    Regtest,
+}
+
+impl Network {
+    // Returns the network as known by the IC Bitcoin API
+    // The IC Bitcoin API serves testnet4 under the Testnet name
+    pub fn bitcoin_network(&self) -> BitcoinNetwork {
+        match self {
+            Network::Mainnet => BitcoinNetwork::Mainnet,
+            Network::Testnet4 => BitcoinNetwork::Testnet,
+            Network::Regtest => BitcoinNetwork::Regtest,
+        }
+    }
+
+    // Returns the network used to encode pool addresses
+    pub fn address_network(&self) -> ree_types::bitcoin::Network {
+        match self {
+            Network::Mainnet => ree_types::bitcoin::Network::Bitcoin,
+            Network::Testnet4 => ree_types::bitcoin::Network::Testnet4,
+            Network::Regtest => ree_types::bitcoin::Network::Regtest,
+        }
+    }
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize, Serialize, Default)]
+// Config holds the deployment settings of the exchange, provided as the canister init argument
+pub struct Config {
+    pub network: Network,
+    // Principal allowed to call the orchestrator endpoints
+    // Defaults to the REE testnet4 orchestrator when not set, which is only allowed on testnet4
+    pub orchestrator: Option<Principal>,
+}
+
+impl Config {
+    // Verifies an orchestrator is known for the network
+    pub fn validate(&self) -> Result<(), String> {
+        (self.orchestrator.is_some() || self.network == Network::Testnet4)
+            .then(|| ())
+            .ok_or(format!(
+                "an orchestrator must be configured for {:?}",
+                self.network
+            ))
+    }
+}
+
+impl Versioned for Config {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for Config {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Config");
+        dire
+    }
+}
+
+// Guard restricting the orchestrator endpoints to the configured orchestrator
+// Without one, only the REE testnet4 orchestrator is trusted, and only on testnet4
+pub fn ensure_orchestrator() -> Result<(), String> {
+    let config = crate::get_config();
+    match config.orchestrator {
+        Some(orchestrator) => (ic_cdk::api::caller() == orchestrator)
+            .then(|| ())
+            .ok_or("Access denied".to_string()),
+        None if config.network == Network::Testnet4 => ensure_testnet4_orchestrator(),
+        None => Err(format!(
+            "No orchestrator configured for {:?}",
+            config.network
+        )),
+    }
+}
+
+#[query]
+// get_config returns the deployment settings of the exchange
+fn get_config() -> Config {
+    crate::get_config()
+}
@@ -0,0 +1,106 @@
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize};
+use ic_cdk_macros::query;
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::{CoinBalance, Txid};
+use serde::Serialize;
+
+/// maximum number of events returned by a single get_events call
+pub const MAX_EVENTS_PER_QUERY: u64 = 1000;
+
+#[derive(Clone, Copy, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// EventKind identifies the pool state transition recorded by an Event
+pub enum EventKind {
+    Deposit,     // A deposit was executed, creating a new pool state
+    Withdraw,    // A withdrawal was executed, creating a new pool state
+    Borrow,      // A borrow was executed, creating a new pool state
+    Repay,       // A repayment was executed, creating a new pool state
+    Liquidate,   // A liquidation was executed, creating a new pool state
+    Consolidate, // The pool UTXOs were merged into one, creating a new pool state
+    Rollback,    // The orchestrator rejected the transaction and its pool states were removed
+    Confirm,     // The transaction was included in a block
+    Reorg,       // The block including the transaction was reorged out
+    Drop,        // The reorged transaction was rolled back before being confirmed again
+    Finalize,    // The transaction is beyond reorg risk and its pool state became the base state
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize, Serialize)]
+// Event records a state transition of a pool in the EVENTS log
+pub struct Event {
+    pub seq: u64,       // Position of the event in the log
+    pub timestamp: u64, // Time (in nanoseconds) the event was recorded
+    pub kind: EventKind,
+    pub txid: Txid,
+    pub pool_address: String,
+    pub inputs: Vec<CoinBalance>, // Coins sent to the pool by the transaction
+    pub outputs: Vec<CoinBalance>, // Coins released by the pool in the transaction
+    pub block_height: Option<u32>, // Height of the block the event relates to, if any
+}
+
+impl Event {
+    pub fn new(kind: EventKind, txid: Txid, pool_address: &String) -> Self {
+        Self {
+            seq: 0,
+            timestamp: ic_cdk::api::time(),
+            kind,
+            txid,
+            pool_address: pool_address.clone(),
+            inputs: vec![],
+            outputs: vec![],
+            block_height: None,
+        }
+    }
+}
+
+impl Versioned for Event {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for Event {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Event");
+        dire
+    }
+}
+
+// Appends an event to the log, numbering it with its position
+pub fn record(mut event: Event) {
+    crate::EVENTS.with_borrow_mut(|log| {
+        event.seq = log.len();
+        if let Err(e) = log.append(&event) {
+            ic_cdk::println!("Failed to record event {:?}: {:?}", event, e);
+        }
+    });
+}
+
+// Records the same event for each of the pools affected by a transaction
+pub fn record_for_pools(
+    kind: EventKind,
+    txid: Txid,
+    pools: &Vec<String>,
+    block_height: Option<u32>,
+) {
+    for pool_address in pools {
+        let mut event = Event::new(kind, txid, pool_address);
+        event.block_height = block_height;
+        record(event);
+    }
+}
+
+#[query]
+// get_events returns up to limit events starting at sequence number from
+// Indexers can follow the log by passing the seq of the last event received plus one
+pub fn get_events(from: u64, limit: u64) -> Vec<Event> {
+    crate::EVENTS.with_borrow(|log| {
+        let to = log
+            .len()
+            .min(from.saturating_add(limit.min(MAX_EVENTS_PER_QUERY)));
+        (from..to).filter_map(|seq| log.get(seq)).collect()
+    })
+}
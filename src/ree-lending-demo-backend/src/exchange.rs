@@ -1,11 +1,15 @@
-use crate::ExecuteTxGuard;
-use crate::pool;
-use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+use crate::config::ensure_orchestrator;
+use crate::events::{self, Event, EventKind};
+use crate::loan::Loan;
+use crate::lp::LpPosition;
+use crate::pool::{self, Pool, PoolState};
+use crate::{ExecuteTxError, ExecuteTxGuard};
 use ic_cdk_macros::{query, update};
-use ree_types::orchestrator_interfaces::ensure_testnet4_orchestrator;
 use ree_types::{
-    CoinBalance, Intention, bitcoin::psbt::Psbt, exchange_interfaces::*, psbt::ree_pool_sign,
+    CoinBalance, Intention, Txid, Utxo, bitcoin::psbt::Psbt, exchange_interfaces::*,
+    psbt::ree_pool_sign,
 };
+use std::collections::HashSet;
 
 #[query]
 // Returns a list of all lending pools
@@ -33,88 +37,64 @@ pub fn get_pool_info(args: GetPoolInfoArgs) -> GetPoolInfoResponse {
         key_derivation_path: vec![p.meta.id.to_bytes()],
         address: p.addr.clone(),
         nonce: p.states.last().map(|s| s.nonce).unwrap_or_default(),
-        btc_reserved: p.states.last().map(|s| s.btc_supply()).unwrap_or_default(),
-        coin_reserved: p
+        btc_reserved: p
             .states
             .last()
-            .map(|s| {
-                vec![CoinBalance {
-                    id: p.meta.id,
-                    value: s.rune_supply(p.meta.id) as u128,
-                }]
-            })
+            .and_then(|s| s.btc_supply().ok())
             .unwrap_or_default(),
-        utxos: p
+        coin_reserved: p
             .states
             .last()
-            .and_then(|s| s.utxo.clone())
-            .map(|utxo| vec![utxo])
+            .and_then(|s| {
+                Some(vec![CoinBalance {
+                    id: p.meta.id,
+                    value: s.rune_supply(p.meta.id).ok()?,
+                }])
+            })
             .unwrap_or_default(),
-        attributes: p.attrs(),
+        utxos: p.states.last().map(|s| s.utxos.clone()).unwrap_or_default(),
+        attributes: p.attrs(crate::get_pool_debt(&p), crate::get_pool_shares(&p.addr)),
     })
 }
 
 #[query]
 // Returns the minimum transaction value required for acceptance by the exchange
-// Normally, the difficulty (minimal value) increases as zero_confirmed_tx_queue_length grows
+// The difficulty (minimal value) increases as zero_confirmed_tx_queue_length grows,
+// following the pool's MinTxValueCurve
 // Longer queues require higher transaction values to prevent spam and congestion
-fn get_minimal_tx_value(_args: GetMinimalTxValueArgs) -> GetMinimalTxValueResponse {
-    // In this demo implementation, the minimal value is fixed
-    // In a production environment, this would scale based on _args.zero_confirmed_tx_queue_length
-    pool::MIN_BTC_VALUE
+fn get_minimal_tx_value(args: GetMinimalTxValueArgs) -> GetMinimalTxValueResponse {
+    crate::get_pool(&args.pool_address)
+        .map(|p| p.minimal_tx_value(args.zero_confirmed_tx_queue_length))
+        .unwrap_or(pool::MIN_BTC_VALUE)
 }
 
-#[update(guard = "ensure_testnet4_orchestrator")]
+#[update(guard = "ensure_orchestrator")]
 // Accepts notifications from the orchestrator to roll back rejected transactions
 // When a transaction is rejected, this function returns the pool to its previous state
 // Only the orchestrator can call this function (ensured by the guard)
 pub fn rollback_tx(args: RollbackTxArgs) -> RollbackTxResponse {
-    let result = crate::TX_RECORDS.with_borrow_mut(|m| {
-        // Look up the transaction record (both confirmed and unconfirmed)
-        let maybe_unconfirmed_record = m.get(&(args.txid.clone(), false));
-        let maybe_confirmed_record = m.get(&(args.txid.clone(), true));
-        let record = maybe_confirmed_record
-            .or(maybe_unconfirmed_record)
-            .ok_or(format!("No record found for txid: {}", args.txid))?;
-
-        ic_cdk::println!(
-            "rollback txid: {} with pools: {:?}",
-            args.txid,
-            record.pools
-        );
-
-        // Roll back each affected pool to its state before this transaction
-        record.pools.iter().for_each(|pool_address| {
-            crate::LENDING_POOLS.with_borrow_mut(|pools| {
-                if let Some(mut pool) = pools.get(pool_address) {
-                    if let Err(e) = pool.rollback(args.txid) {
-                        ic_cdk::println!("Rollback failed: {:?}", e);
-                    } else {
-                        pools.insert(pool_address.clone(), pool);
-                    }
-                } else {
-                    ic_cdk::println!("Pool not found: {}", pool_address);
-                }
-            });
-        });
-
-        m.remove(&(args.txid.clone(), false));
-        m.remove(&(args.txid.clone(), true));
-
-        Ok(())
-    });
-
-    result
+    // Roll back each affected pool to its state before this transaction,
+    // along with the transactions that spent the pool UTXOs it created
+    // A transaction unconfirmed by a reorg is dropped, at the height of the block it was reorged from
+    let reorged_height = crate::REORGED_TXIDS.with_borrow(|r| r.get(&args.txid));
+    let kind = match reorged_height {
+        Some(_) => EventKind::Drop,
+        None => EventKind::Rollback,
+    };
+    crate::rollback_with_dependents(args.txid, kind, reorged_height)
+        .then(|| ())
+        .ok_or(format!("No record found for txid: {}", args.txid))
 }
 
-#[update(guard = "ensure_testnet4_orchestrator")]
+#[update(guard = "ensure_orchestrator")]
 // Accepts notifications from the orchestrator about newly confirmed blocks
 // Used to finalize transactions and handle blockchain reorganizations (reorgs)
 // All exchanges implement this interface in the same way - will be moved to SDK in the future
 // Only the orchestrator can call this function (ensured by the guard)
 pub fn new_block(args: NewBlockArgs) -> NewBlockResponse {
+    let network = crate::get_config().network.bitcoin_network();
     // Check for blockchain reorganizations
-    match crate::reorg::detect_reorg(BitcoinNetwork::Testnet, args.clone()) {
+    match crate::reorg::detect_reorg(network, args.clone()) {
         Ok(_) => {}
         Err(crate::reorg::Error::DuplicateBlock { height, hash }) => {
             ic_cdk::println!(
@@ -123,9 +103,16 @@ pub fn new_block(args: NewBlockArgs) -> NewBlockResponse {
                 hash
             );
         }
+        Err(crate::reorg::Error::Gap { from, to }) => {
+            // Accept the new tip and reconcile the missing blocks if they arrive later
+            crate::reorg::record_gap(from, to);
+        }
         Err(crate::reorg::Error::Unrecoverable) => {
             return Err("Unrecoverable reorg detected".to_string());
         }
+        Err(e @ crate::reorg::Error::Unlinked { .. }) => {
+            return Err(e.to_string());
+        }
         Err(crate::reorg::Error::Recoverable { height, depth }) => {
             crate::reorg::handle_reorg(height, depth);
         }
@@ -143,18 +130,30 @@ pub fn new_block(args: NewBlockArgs) -> NewBlockResponse {
         ic_cdk::println!("new block {} inserted into blocks", block_height,);
     });
 
+    // Accrue interest on the loans of every pool up to the new block
+    crate::LENDING_POOLS.with_borrow_mut(|p| {
+        let pools = p.iter().map(|(_, pool)| pool).collect::<Vec<_>>();
+        for mut pool in pools {
+            let borrowed = crate::get_pool_debt(&pool);
+            pool.accrue_interest(block_height, borrowed);
+            p.insert(pool.addr.clone(), pool);
+        }
+    });
+
     // Mark transactions as confirmed
     for txid in confirmed_txids {
-        crate::TX_RECORDS.with_borrow_mut(|m| {
-            if let Some(record) = m.remove(&(txid.clone(), false)) {
-                m.insert((txid.clone(), true), record.clone());
-                ic_cdk::println!("confirm txid: {} with pools: {:?}", txid, record.pools);
-            }
-        });
+        if let Some(record) = crate::write_tx_record((txid.clone(), false), None) {
+            crate::write_tx_record((txid.clone(), true), Some(record.clone()));
+            events::record_for_pools(EventKind::Confirm, txid, &record.pools, Some(block_height));
+            ic_cdk::println!("confirm txid: {} with pools: {:?}", txid, record.pools);
+        }
+        // A transaction unconfirmed by a reorg may be confirmed again in the replacement chain
+        crate::REORGED_TXIDS.with_borrow_mut(|r| r.remove(&txid));
     }
     // Calculate the height below which blocks are considered fully confirmed (beyond reorg risk)
+    // Saturates on the first blocks of a chain (e.g. on regtest), where nothing is final yet
     let confirmed_height =
-        block_height - crate::reorg::get_max_recoverable_reorg_depth(BitcoinNetwork::Testnet) + 1;
+        (block_height + 1).saturating_sub(crate::reorg::get_max_recoverable_reorg_depth(network));
 
     // Finalize transactions in confirmed blocks
     crate::BLOCKS.with_borrow(|m| {
@@ -163,34 +162,51 @@ pub fn new_block(args: NewBlockArgs) -> NewBlockResponse {
             .for_each(|(height, block_info)| {
                 ic_cdk::println!("finalizing txs in block: {}", height);
                 block_info.confirmed_txids.iter().for_each(|txid| {
-                    crate::TX_RECORDS.with_borrow_mut(|m| {
-                        if let Some(record) = m.get(&(txid.clone(), true)) {
-                            ic_cdk::println!(
-                                "finalize txid: {} with pools: {:?}",
-                                txid,
-                                record.pools
-                            );
-                            // Make transaction state permanent in each affected pool
-                            record.pools.iter().for_each(|pool_address| {
-                                crate::LENDING_POOLS.with_borrow_mut(|p| {
-                                    if let Some(mut pool) = p.get(pool_address) {
-                                        if let Err(e) = pool.finalize(txid.clone()) {
-                                            ic_cdk::println!("Finalize failed: {:?}", e);
-                                        } else {
+                    if let Some(record) =
+                        crate::TX_RECORDS.with_borrow(|m| m.get(&(txid.clone(), true)))
+                    {
+                        ic_cdk::println!("finalize txid: {} with pools: {:?}", txid, record.pools);
+                        // Make transaction state permanent in each affected pool
+                        record.pools.iter().for_each(|pool_address| {
+                            crate::LENDING_POOLS.with_borrow_mut(|p| {
+                                if let Some(mut pool) = p.get(pool_address) {
+                                    match pool.finalize(txid.clone()) {
+                                        Ok(superseded) => {
                                             p.insert(pool_address.clone(), pool);
+                                            crate::finalize_loans(pool_address, txid.clone());
+                                            crate::finalize_lp_positions(
+                                                pool_address,
+                                                txid.clone(),
+                                            );
+                                            // Earlier states were mined before this one, even when
+                                            // they were confirmed in a block the exchange never received
+                                            for earlier in superseded {
+                                                finalize_superseded(pool_address, earlier, height);
+                                            }
                                         }
-                                    } else {
-                                        ic_cdk::println!("Pool not found: {}", pool_address);
+                                        Err(e) => ic_cdk::println!("Finalize failed: {:?}", e),
                                     }
-                                });
+                                } else {
+                                    ic_cdk::println!("Pool not found: {}", pool_address);
+                                }
                             });
-                            m.remove(&(txid.clone(), true));
-                        }
-                    });
+                        });
+                        events::record_for_pools(
+                            EventKind::Finalize,
+                            *txid,
+                            &record.pools,
+                            Some(height),
+                        );
+                        crate::write_tx_record((txid.clone(), true), None);
+                        crate::SIGNED_PSBTS.with_borrow_mut(|s| s.remove(txid));
+                    }
                 });
             });
     });
 
+    // Blocks that are still missing at a final height will never be processed
+    crate::reorg::prune_gaps(confirmed_height);
+
     // Clean up old block data that's no longer needed
     crate::BLOCKS.with_borrow_mut(|m| {
         let heights_to_remove: Vec<u32> = m
@@ -206,29 +222,91 @@ pub fn new_block(args: NewBlockArgs) -> NewBlockResponse {
     Ok(())
 }
 
-#[update(guard = "ensure_testnet4_orchestrator")]
-// Accepts transaction execution requests from the orchestrator
-// Verifies the submitted PSBT (Partially Signed Bitcoin Transaction)
-// If validation passes, signs the pool's UTXOs and updates the exchange pool state
-// Only the orchestrator can call this function (ensured by the guard)
-pub async fn execute_tx(args: ExecuteTxArgs) -> ExecuteTxResponse {
-    let ExecuteTxArgs {
-        psbt_hex,
-        txid,
-        intention_set,
-        intention_index,
-        zero_confirmed_tx_queue_length: _zero_confirmed_tx_queue_length,
-    } = args;
-    // Decode and deserialize the PSBT
-    let raw = hex::decode(&psbt_hex).map_err(|_| "invalid psbt".to_string())?;
-    let mut psbt = Psbt::deserialize(raw.as_slice()).map_err(|_| "invalid psbt".to_string())?;
+// Finalizes the loans and LP positions changed in a pool by a transaction whose state was removed
+// by the finalization of a later state
+// Forgets its unconfirmed record, left behind when the transaction was confirmed in a missing block;
+// a confirmed record is removed when its own block is finalized
+fn finalize_superseded(pool_address: &String, txid: Txid, height: u32) {
+    crate::finalize_loans(pool_address, txid);
+    crate::finalize_lp_positions(pool_address, txid);
+    if crate::write_tx_record((txid, false), None).is_some() {
+        ic_cdk::println!(
+            "finalize txid: {} superseded in pool: {}",
+            txid,
+            pool_address
+        );
+        crate::REORGED_TXIDS.with_borrow_mut(|r| r.remove(&txid));
+        crate::SIGNED_PSBTS.with_borrow_mut(|s| s.remove(&txid));
+        events::record_for_pools(
+            EventKind::Finalize,
+            txid,
+            &vec![pool_address.clone()],
+            Some(height),
+        );
+    }
+}
 
-    // Extract the intention details
-    let intention = intention_set.intentions[intention_index as usize].clone();
+// Ledger is the loan or LP position changed by an intention alongside its pool
+enum Ledger {
+    Loan(Loan),
+    LpPosition(LpPosition),
+}
+
+// Execution is the outcome of validating one intention, applied once the PSBT is signed
+struct Execution {
+    pool: Pool,
+    new_state: PoolState,
+    consumed: Vec<Utxo>,    // Pool UTXOs spent by the transaction, to be signed
+    ledger: Option<Ledger>, // None when the intention changes no loan or position
+    event: Event,
+}
+
+// Returns the latest state of a loan or LP position computed by an execution, on top of the stored
+// loan or position, provided its latest state is still the one the execution was built on
+fn rebase_ledger(ledger: Ledger) -> Result<Ledger, ExecuteTxError> {
+    // Returns true if the stored states end with the state preceding the computed one
+    fn builds_on<T: PartialEq>(stored: Option<&[T]>, computed: &[T]) -> bool {
+        stored.and_then(|states| states.last()) == computed.iter().rev().nth(1)
+    }
+    match ledger {
+        Ledger::Loan(loan) => {
+            let stored = crate::get_loan(&loan.pool_address, &loan.borrower);
+            builds_on(stored.as_ref().map(|l| l.states.as_slice()), &loan.states)
+                .then(|| ())
+                .ok_or(ExecuteTxError::StateChanged(loan.pool_address.clone()))?;
+            let mut rebased =
+                stored.unwrap_or_else(|| Loan::new(&loan.pool_address, &loan.borrower));
+            rebased.commit(loan.states.last().cloned().unwrap_or_default());
+            Ok(Ledger::Loan(rebased))
+        }
+        Ledger::LpPosition(position) => {
+            let stored = crate::get_lp_position(&position.pool_address, &position.owner);
+            builds_on(
+                stored.as_ref().map(|p| p.states.as_slice()),
+                &position.states,
+            )
+            .then(|| ())
+            .ok_or(ExecuteTxError::StateChanged(position.pool_address.clone()))?;
+            let mut rebased =
+                stored.unwrap_or_else(|| LpPosition::new(&position.pool_address, &position.owner));
+            rebased.commit(position.states.last().cloned().unwrap_or_default());
+            Ok(Ledger::LpPosition(rebased))
+        }
+    }
+}
+
+// Validates an intention against its pool and computes the changes it makes
+fn validate_intention(
+    pool: Pool,
+    txid: Txid,
+    intention: Intention,
+    initiator: &String,
+    zero_confirmed_tx_queue_length: u32,
+) -> Result<Execution, ExecuteTxError> {
     let Intention {
         exchange_id: _,
-        action: _,
-        action_params: _,
+        action,
+        action_params,
         pool_address,
         nonce,
         pool_utxo_spent,
@@ -237,96 +315,330 @@ pub async fn execute_tx(args: ExecuteTxArgs) -> ExecuteTxResponse {
         output_coins,
     } = intention;
 
-    let _guard = ExecuteTxGuard::new(pool_address.clone())
-        .ok_or(format!("Pool {0} Executing", pool_address).to_string())?;
+    // Refuse the actions halted by the controllers
+    crate::pause::ensure_not_paused(&pool_address, &action)?;
 
-    // Get the pool from storage
-    let pool = crate::LENDING_POOLS
-        .with_borrow(|m| m.get(&pool_address).expect("already checked in pre_*; qed"));
+    // Keep the coins moved by the transaction for the event log
+    let inputs: Vec<CoinBalance> = input_coins.iter().map(|c| c.coin.clone()).collect();
+    let outputs: Vec<CoinBalance> = output_coins.iter().map(|c| c.coin.clone()).collect();
 
-    // Process the transaction based on the action type
-    match intention.action.as_ref() {
+    // Process the intention based on the action type
+    let (new_state, consumed, ledger, kind) = match action.as_ref() {
         "deposit" => {
-            // Validate the deposit transaction and get the new pool state
-            let (new_state, consumed) = pool
-                .validate_deposit(
-                    txid,
-                    nonce,
-                    pool_utxo_spent,
-                    pool_utxo_received,
-                    input_coins,
-                    output_coins,
-                )
-                .map_err(|e| e.to_string())?;
-
-            // Sign the UTXO if there's an existing one to spend
-            if let Some(ref utxo) = consumed {
-                ree_pool_sign(
-                    &mut psbt,
-                    vec![utxo],
-                    crate::SCHNORR_KEY_NAME,
-                    pool.derivation_path(),
-                )
-                .await
-                .map_err(|e| e.to_string())?;
-            }
-
-            // Update the pool with the new state
-            crate::LENDING_POOLS.with_borrow_mut(|m| {
-                let mut pool = m
-                    .get(&pool_address)
-                    .expect("already checked in pre_deposit; qed");
-                pool.commit(new_state);
-                m.insert(pool_address.clone(), pool);
-            });
+            // Validate the deposit and get the new pool state and LP position
+            let (new_state, consumed, position) = pool.validate_deposit(
+                txid,
+                nonce,
+                initiator,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+                zero_confirmed_tx_queue_length,
+            )?;
+            (
+                new_state,
+                consumed,
+                Some(Ledger::LpPosition(position)),
+                EventKind::Deposit,
+            )
+        }
+        "withdraw" => {
+            // Validate the withdrawal and get the new pool state and LP position
+            let (new_state, consumed, position) = pool.validate_withdraw(
+                txid,
+                nonce,
+                initiator,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+            )?;
+            (
+                new_state,
+                consumed,
+                Some(Ledger::LpPosition(position)),
+                EventKind::Withdraw,
+            )
         }
         "borrow" => {
-            // Validate the borrow transaction and get the new pool state
-            let (new_state, consumed) = pool
-                .validate_borrow(
-                    txid,
-                    nonce,
-                    pool_utxo_spent,
-                    pool_utxo_received,
-                    input_coins,
-                    output_coins,
-                )
-                .map_err(|e| e.to_string())?;
-
-            // Sign the UTXO to be spent
+            // Value the collateral with the latest price, refusing to proceed if it is stale
+            let price = crate::oracle::collateral_price(&pool.base_id())?;
+            // Validate the borrow and get the new pool state and loan
+            let (new_state, consumed, loan) = pool.validate_borrow(
+                txid,
+                nonce,
+                initiator,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+                &price,
+                zero_confirmed_tx_queue_length,
+            )?;
+            (
+                new_state,
+                consumed,
+                Some(Ledger::Loan(loan)),
+                EventKind::Borrow,
+            )
+        }
+        "repay" => {
+            // Validate the repayment and get the new pool state and loan
+            let (new_state, consumed, loan) = pool.validate_repay(
+                txid,
+                nonce,
+                initiator,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+            )?;
+            (
+                new_state,
+                consumed,
+                Some(Ledger::Loan(loan)),
+                EventKind::Repay,
+            )
+        }
+        "liquidate" => {
+            // Value the collateral with the latest price, refusing to proceed if it is stale
+            let price = crate::oracle::collateral_price(&pool.base_id())?;
+            // Validate the liquidation and get the new pool state and the borrower's loan
+            let (new_state, consumed, loan) = pool.validate_liquidate(
+                txid,
+                nonce,
+                action_params,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+                &price,
+            )?;
+            (
+                new_state,
+                consumed,
+                Some(Ledger::Loan(loan)),
+                EventKind::Liquidate,
+            )
+        }
+        "consolidate" => {
+            // Validate the merge of the pool UTXOs and get the new pool state
+            let (new_state, consumed) = pool.validate_consolidate(
+                txid,
+                nonce,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+            )?;
+            (new_state, consumed, None, EventKind::Consolidate)
+        }
+        _ => {
+            return Err(ExecuteTxError::InvalidAction(action));
+        }
+    };
+
+    Ok(Execution {
+        pool,
+        new_state,
+        consumed,
+        ledger,
+        event: Event {
+            inputs,
+            outputs,
+            ..Event::new(kind, txid, &pool_address)
+        },
+    })
+}
+
+#[update(guard = "ensure_orchestrator")]
+// Accepts transaction execution requests from the orchestrator
+// Verifies the submitted PSBT (Partially Signed Bitcoin Transaction)
+// If validation passes, signs the pool's UTXOs and updates the exchange pool state
+// All the intentions of the set addressed to this exchange are executed together, so a transaction
+// can for instance deposit collateral in one pool and borrow BTC from another
+// Only the orchestrator can call this function (ensured by the guard)
+pub async fn execute_tx(args: ExecuteTxArgs) -> ExecuteTxResponse {
+    // The response type is shared by all exchanges, so errors are returned serialized as JSON
+    // e.g. {"UnknownPool":"bc1p..."} or {"ValidationFailed":"TooSmallFunds"}
+    execute(args)
+        .await
+        .map_err(|e| serde_json::to_string(&e).unwrap_or_else(|_| e.to_string()))
+}
+
+async fn execute(args: ExecuteTxArgs) -> Result<String, ExecuteTxError> {
+    let ExecuteTxArgs {
+        psbt_hex,
+        txid,
+        intention_set,
+        intention_index,
+        zero_confirmed_tx_queue_length,
+    } = args;
+    // Decode and deserialize the PSBT
+    let raw = hex::decode(&psbt_hex).map_err(|e| ExecuteTxError::InvalidPsbt(e.to_string()))?;
+    let mut psbt = Psbt::deserialize(raw.as_slice())
+        .map_err(|e| ExecuteTxError::InvalidPsbt(e.to_string()))?;
+
+    // The initiator signed the transaction and is the only one who can act on their own positions
+    let initiator = intention_set.initiator_address.clone();
+
+    // Collect the intentions addressed to this exchange
+    let exchange_id = intention_set
+        .intentions
+        .get(intention_index as usize)
+        .ok_or(ExecuteTxError::InvalidIntentionIndex(intention_index))?
+        .exchange_id
+        .clone();
+    let intentions: Vec<(usize, Intention)> = intention_set
+        .intentions
+        .iter()
+        .cloned()
+        .enumerate()
+        .filter(|(_, intention)| intention.exchange_id == exchange_id)
+        .collect();
+
+    // The whole group is executed when the orchestrator submits its first intention,
+    // and the signatures of every pool of the group are added to the PSBT submitted then
+    // For the later intentions, they are merged again into the PSBT submitted, which may have been
+    // rebuilt from the unsigned transaction or carry the signatures of other exchanges since
+    if intentions
+        .first()
+        .is_some_and(|(index, _)| *index != intention_index as usize)
+    {
+        let signed = crate::SIGNED_PSBTS
+            .with_borrow(|m| m.get(&txid))
+            .ok_or(ExecuteTxError::GroupNotExecuted)?;
+        let signed = hex::decode(&signed)
+            .ok()
+            .and_then(|raw| Psbt::deserialize(raw.as_slice()).ok())
+            .ok_or(ExecuteTxError::InvalidPsbt(
+                "failed to decode the signed PSBT".to_string(),
+            ))?;
+        psbt.combine(signed)
+            .map_err(|e| ExecuteTxError::InvalidPsbt(e.to_string()))?;
+        return Ok(psbt.serialize_hex());
+    }
+
+    // A transaction can change each pool only once, as every intention builds on the latest pool state
+    let pool_addresses: Vec<String> = intentions
+        .iter()
+        .map(|(_, intention)| intention.pool_address.clone())
+        .collect();
+    (pool_addresses.iter().collect::<HashSet<_>>().len() == pool_addresses.len())
+        .then(|| ())
+        .ok_or(ExecuteTxError::DuplicatePool)?;
+
+    // Lock every pool of the group until the execution completes
+    let mut guards = vec![];
+    for pool_address in pool_addresses.iter() {
+        guards.push(
+            ExecuteTxGuard::new(pool_address.clone())
+                .ok_or(ExecuteTxError::PoolExecuting(pool_address.clone()))?,
+        );
+    }
+
+    // Get the pools from storage
+    let mut pools = vec![];
+    for (_, intention) in intentions.iter() {
+        pools.push(
+            crate::get_pool(&intention.pool_address)
+                .ok_or(ExecuteTxError::UnknownPool(intention.pool_address.clone()))?,
+        );
+    }
+
+    // Validate every intention before signing anything
+    let mut executions = vec![];
+    for (pool, (_, intention)) in pools.iter().zip(intentions.iter()) {
+        executions.push(validate_intention(
+            pool.clone(),
+            txid,
+            intention.clone(),
+            &initiator,
+            zero_confirmed_tx_queue_length,
+        )?);
+    }
+
+    // Check the PSBT is the transaction described by the intentions, and that it moves into the
+    // pools the assets of their new states
+    let described: Vec<(&Pool, &Intention, &PoolState)> = pools
+        .iter()
+        .zip(intentions.iter().map(|(_, intention)| intention))
+        .zip(executions.iter().map(|execution| &execution.new_state))
+        .map(|((pool, intention), state)| (pool, intention, state))
+        .collect();
+    crate::psbt::verify_psbt(&psbt, txid, &described, &intention_set)?;
+
+    // Sign the UTXOs to be spent with the key of their pool
+    for execution in executions.iter() {
+        if !execution.consumed.is_empty() {
             ree_pool_sign(
                 &mut psbt,
-                vec![&consumed],
+                execution.consumed.iter().collect(),
                 crate::SCHNORR_KEY_NAME,
-                pool.derivation_path(),
+                execution.pool.derivation_path(),
             )
             .await
-            .map_err(|e| e.to_string())?;
-
-            // Update the pool with the new state
-            crate::LENDING_POOLS.with_borrow_mut(|m| {
-                let mut pool = m
-                    .get(&pool_address)
-                    .expect("already checked in pre_borrow; qed");
-                pool.commit(new_state);
-                m.insert(pool_address.clone(), pool);
-            });
+            .map_err(|e| ExecuteTxError::SigningFailed(e.to_string()))?;
         }
-        _ => {
-            return Err("invalid method".to_string());
+    }
+
+    // The stored pools, loans and LP positions are reloaded, as new blocks and rollbacks do not wait
+    // for the pool guards: the borrow index may have moved while signing, and states may have been
+    // rolled back or finalized. Nothing is committed, and the signed PSBT is not returned,
+    // unless every pool, loan and LP position is still in the state the execution was validated on
+    let mut commits = vec![];
+    for execution in executions {
+        let Execution {
+            pool,
+            new_state,
+            consumed: _,
+            ledger,
+            event,
+        } = execution;
+        let stored =
+            crate::get_pool(&pool.addr).ok_or(ExecuteTxError::UnknownPool(pool.addr.clone()))?;
+        let latest = |pool: &Pool| pool.states.last().map(|state| (state.nonce, state.id));
+        (latest(&stored) == latest(&pool))
+            .then(|| ())
+            .ok_or(ExecuteTxError::StateChanged(pool.addr.clone()))?;
+        let ledger = ledger.map(rebase_ledger).transpose()?;
+        commits.push((stored, new_state, ledger, event));
+    }
+
+    for (mut pool, new_state, ledger, event) in commits {
+        // Update the pool with the new state
+        pool.commit(new_state);
+        crate::LENDING_POOLS.with_borrow_mut(|m| {
+            m.insert(pool.addr.clone(), pool);
+        });
+        // Record the shares minted or burned, or the updated position of the borrower
+        match ledger {
+            Some(Ledger::Loan(loan)) => crate::save_loan(loan),
+            Some(Ledger::LpPosition(position)) => crate::save_lp_position(position),
+            None => {}
         }
+        events::record(event);
     }
 
     // Record the transaction as unconfirmed and track which pools it affects
-    crate::TX_RECORDS.with_borrow_mut(|m| {
-        ic_cdk::println!("new unconfirmed txid: {} in pool: {} ", txid, pool_address);
-        let mut record = m.get(&(txid.clone(), false)).unwrap_or_default();
+    ic_cdk::println!(
+        "new unconfirmed txid: {} in pools: {:?} ",
+        txid,
+        pool_addresses
+    );
+    let mut record = crate::TX_RECORDS
+        .with_borrow(|m| m.get(&(txid.clone(), false)))
+        .unwrap_or_default();
+    for pool_address in pool_addresses {
         if !record.pools.contains(&pool_address) {
-            record.pools.push(pool_address.clone());
+            record.pools.push(pool_address);
         }
-        m.insert((txid.clone(), false), record);
-    });
+    }
+    crate::write_tx_record((txid.clone(), false), Some(record));
 
     // Return the serialized PSBT with the exchange's signatures
-    Ok(psbt.serialize_hex())
+    let signed = psbt.serialize_hex();
+    crate::SIGNED_PSBTS.with_borrow_mut(|m| m.insert(txid, signed.clone()));
+    Ok(signed)
 }
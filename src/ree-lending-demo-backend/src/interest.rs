@@ -0,0 +1,262 @@
+use crate::pool::MAX_BPS;
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// borrow indexes are fixed-point numbers scaled by 10^18
+pub const INDEX_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// expected number of Bitcoin blocks per year (one block every 10 minutes)
+pub const BLOCKS_PER_YEAR: u128 = 52_560;
+
+/// highest annual borrow rate a rate model can reach, 100000 = 1000%
+pub const MAX_RATE_BPS: u64 = 100_000;
+
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// BorrowIndex tracks the interest accumulated by a pool since its creation
+// A loan stores its debt divided by the index at borrow time (scaled debt),
+// so its current debt is always scaled debt * current index
+pub struct BorrowIndex {
+    pub value: u128, // Accumulated interest factor, scaled by INDEX_SCALE
+    pub height: u32, // Height of the last block interest was accrued to (0 before the first block)
+}
+
+impl Default for BorrowIndex {
+    fn default() -> Self {
+        Self {
+            value: INDEX_SCALE,
+            height: 0,
+        }
+    }
+}
+
+impl BorrowIndex {
+    // Accrues simple interest at the annual rate (in basis points) for the blocks since the last accrual
+    // Blocks at or below the last accrued height (duplicates and reorgs) never accrue twice
+    pub fn accrue(&mut self, rate_bps: u64, height: u32) {
+        if self.height == 0 || height <= self.height {
+            self.height = self.height.max(height);
+            return;
+        }
+        let blocks = (height - self.height) as u128;
+        let interest = self
+            .value
+            .checked_mul(rate_bps as u128)
+            .and_then(|v| v.checked_mul(blocks))
+            .map(|v| v / (MAX_BPS as u128 * BLOCKS_PER_YEAR));
+        match interest.and_then(|i| self.value.checked_add(i)) {
+            Some(value) => self.value = value,
+            None => ic_cdk::println!("borrow index overflow at height {}", height),
+        }
+        self.height = height;
+    }
+
+    // Converts a BTC amount into scaled debt, rounded up in favor of the pool
+    pub fn to_scaled(&self, sats: u64) -> Option<u128> {
+        (sats as u128)
+            .checked_mul(INDEX_SCALE)
+            .map(|v| v.div_ceil(self.value))
+    }
+
+    // Converts a repaid BTC amount into scaled debt, rounded down in favor of the pool
+    pub fn to_scaled_down(&self, sats: u64) -> Option<u128> {
+        (sats as u128)
+            .checked_mul(INDEX_SCALE)
+            .map(|v| v / self.value)
+    }
+
+    // Converts scaled debt into the BTC amount currently owed, rounded up in favor of the pool
+    pub fn to_debt(&self, scaled: u128) -> Option<u64> {
+        scaled
+            .checked_mul(self.value)
+            .map(|v| v.div_ceil(INDEX_SCALE))
+            .and_then(|v| v.try_into().ok())
+    }
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// RateModel is a kinked utilization curve giving the annual borrow rate of a pool
+// Below the optimal utilization the rate grows slowly with slope1, above it steeply with slope2,
+// so that lenders are paid more when borrowers drain the pool
+pub struct RateModel {
+    pub base_rate_bps: u64,           // Borrow rate at zero utilization
+    pub slope1_bps: u64,              // Rate increase from zero to optimal utilization
+    pub slope2_bps: u64,              // Rate increase from optimal to full utilization
+    pub optimal_utilization_bps: u64, // Utilization at which the curve kinks
+}
+
+impl Default for RateModel {
+    fn default() -> Self {
+        Self {
+            base_rate_bps: 0,
+            slope1_bps: 0,
+            slope2_bps: 0,
+            optimal_utilization_bps: 8000,
+        }
+    }
+}
+
+impl RateModel {
+    // Verifies the curve is well formed and its highest rate does not exceed MAX_RATE_BPS
+    pub fn validate(&self) -> Result<(), String> {
+        if self.optimal_utilization_bps == 0 || self.optimal_utilization_bps > MAX_BPS {
+            return Err(format!(
+                "optimal_utilization_bps must be within (0, {}]",
+                MAX_BPS
+            ));
+        }
+        let max_rate = self
+            .base_rate_bps
+            .checked_add(self.slope1_bps)
+            .and_then(|r| r.checked_add(self.slope2_bps));
+        if max_rate.is_none_or(|r| r > MAX_RATE_BPS) {
+            return Err(format!(
+                "base_rate_bps + slope1_bps + slope2_bps must not exceed {}",
+                MAX_RATE_BPS
+            ));
+        }
+        Ok(())
+    }
+
+    // Returns the annual borrow rate in basis points at the specified utilization
+    // Computed in u128 and capped at u64::MAX, so curves stored before validation existed cannot overflow
+    pub fn borrow_rate(&self, utilization_bps: u64) -> u64 {
+        let utilization = utilization_bps.min(MAX_BPS) as u128;
+        let optimal = self.optimal_utilization_bps.clamp(1, MAX_BPS) as u128;
+        let base = self.base_rate_bps as u128;
+        let slope1 = self.slope1_bps as u128;
+        let slope2 = self.slope2_bps as u128;
+        let rate = if utilization <= optimal {
+            base + slope1 * utilization / optimal
+        } else if optimal == MAX_BPS as u128 {
+            base + slope1
+        } else {
+            base + slope1 + slope2 * (utilization - optimal) / (MAX_BPS as u128 - optimal)
+        };
+        rate.min(u64::MAX as u128) as u64
+    }
+
+    // Returns the annual rate in basis points earned by the pool's BTC supply
+    // Borrowers' interest is spread over all the BTC lent to the pool
+    pub fn supply_rate(&self, utilization_bps: u64) -> u64 {
+        (self.borrow_rate(utilization_bps) as u128 * utilization_bps.min(MAX_BPS) as u128
+            / MAX_BPS as u128) as u64
+    }
+}
+
+// Returns the share (in basis points) of the pool's BTC that is lent out
+// available is the BTC still held by the pool, borrowed the BTC owed by borrowers
+pub fn utilization_bps(available: u64, borrowed: u64) -> u64 {
+    let total = available as u128 + borrowed as u128;
+    if total == 0 {
+        return 0;
+    }
+    (borrowed as u128 * MAX_BPS as u128 / total) as u64
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// PoolRates describes the current interest rates of a pool
+pub struct PoolRates {
+    pub utilization_bps: u64,
+    pub borrow_rate_bps: u64, // Annual rate charged to borrowers
+    pub supply_rate_bps: u64, // Annual rate earned by lenders
+    pub total_borrowed: u64,  // BTC owed by all borrowers, including accrued interest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> RateModel {
+        RateModel {
+            base_rate_bps: 200,
+            slope1_bps: 400,
+            slope2_bps: 6000,
+            optimal_utilization_bps: 8000,
+        }
+    }
+
+    #[test]
+    fn borrow_rate_follows_the_kinked_curve() {
+        let model = model();
+        assert_eq!(model.borrow_rate(0), 200);
+        assert_eq!(model.borrow_rate(4000), 400);
+        assert_eq!(model.borrow_rate(8000), 600);
+        assert_eq!(model.borrow_rate(9000), 3600);
+        assert_eq!(model.borrow_rate(MAX_BPS), 6600);
+        // Utilization above 100% is treated as full utilization
+        assert_eq!(model.borrow_rate(2 * MAX_BPS), 6600);
+    }
+
+    #[test]
+    fn borrow_rate_is_flat_above_a_full_optimal_utilization() {
+        let model = RateModel {
+            optimal_utilization_bps: MAX_BPS,
+            ..model()
+        };
+        assert_eq!(model.borrow_rate(MAX_BPS), 600);
+    }
+
+    #[test]
+    fn supply_rate_spreads_interest_over_the_supply() {
+        let model = model();
+        assert_eq!(model.supply_rate(0), 0);
+        assert_eq!(model.supply_rate(8000), 480);
+        assert_eq!(model.supply_rate(MAX_BPS), 6600);
+    }
+
+    #[test]
+    fn rates_of_unvalidated_curves_do_not_overflow() {
+        let model = RateModel {
+            base_rate_bps: u64::MAX,
+            slope1_bps: u64::MAX,
+            slope2_bps: u64::MAX,
+            optimal_utilization_bps: 1,
+        };
+        assert_eq!(model.borrow_rate(MAX_BPS), u64::MAX);
+        assert_eq!(model.supply_rate(MAX_BPS), u64::MAX);
+    }
+
+    #[test]
+    fn validate_bounds_the_curve() {
+        assert!(model().validate().is_ok());
+        assert!(RateModel::default().validate().is_ok());
+        let too_steep = RateModel {
+            slope2_bps: MAX_RATE_BPS,
+            ..model()
+        };
+        assert!(too_steep.validate().is_err());
+        let overflowing = RateModel {
+            base_rate_bps: u64::MAX,
+            slope1_bps: 1,
+            ..model()
+        };
+        assert!(overflowing.validate().is_err());
+        let no_kink = RateModel {
+            optimal_utilization_bps: 0,
+            ..model()
+        };
+        assert!(no_kink.validate().is_err());
+    }
+
+    #[test]
+    fn utilization_is_the_share_of_lent_btc() {
+        assert_eq!(utilization_bps(0, 0), 0);
+        assert_eq!(utilization_bps(3000, 1000), 2500);
+        assert_eq!(utilization_bps(0, 1000), MAX_BPS);
+    }
+
+    #[test]
+    fn borrow_index_accrues_once_per_block() {
+        let mut index = BorrowIndex::default();
+        // The first block only sets the starting height
+        index.accrue(MAX_BPS, 100);
+        assert_eq!(index.value, INDEX_SCALE);
+        // A whole year at 100% doubles the index
+        index.accrue(MAX_BPS, 100 + BLOCKS_PER_YEAR as u32);
+        assert_eq!(index.value, 2 * INDEX_SCALE);
+        // Replayed blocks accrue nothing
+        index.accrue(MAX_BPS, 100);
+        assert_eq!(index.value, 2 * INDEX_SCALE);
+        assert_eq!(index.to_debt(index.to_scaled(1001).unwrap()), Some(1002));
+    }
+}
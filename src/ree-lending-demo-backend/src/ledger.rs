@@ -0,0 +1,85 @@
+use candid::Deserialize;
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::Txid;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+// LedgerKind tells whether a TxLedgerKey refers to a loan or to an LP position
+pub enum LedgerKind {
+    Loan,
+    LpPosition,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+// TxLedgerKey records that a transaction created a state of a loan or LP position
+// Keys are ordered by transaction and pool, so the positions touched by a transaction in a pool
+// can be found with a range query instead of scanning every position of the pool
+pub struct TxLedgerKey {
+    pub txid: Txid,
+    pub pool_address: String,
+    pub kind: LedgerKind,
+    pub owner: String, // Borrower of the loan or owner of the LP position
+}
+
+impl Storable for TxLedgerKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let mut bytes = vec![];
+        let _ = ciborium::ser::into_writer(self, &mut bytes);
+        std::borrow::Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode TxLedgerKey");
+        dire
+    }
+}
+
+// Records that the transaction created a state of the owner's loan or LP position in the pool
+pub fn index(txid: Txid, pool_address: &String, kind: LedgerKind, owner: &String) {
+    crate::TX_LEDGERS.with_borrow_mut(|m| {
+        m.insert(
+            TxLedgerKey {
+                txid,
+                pool_address: pool_address.clone(),
+                kind,
+                owner: owner.clone(),
+            },
+            (),
+        )
+    });
+}
+
+// Returns the owners of the loans or LP positions of the pool changed by the transaction
+pub fn touched(txid: Txid, pool_address: &String, kind: LedgerKind) -> Vec<String> {
+    let first = TxLedgerKey {
+        txid,
+        pool_address: pool_address.clone(),
+        kind,
+        owner: String::new(),
+    };
+    crate::TX_LEDGERS.with_borrow(|m| {
+        m.range(first..)
+            .take_while(|(key, _)| {
+                key.txid == txid && &key.pool_address == pool_address && key.kind == kind
+            })
+            .map(|(key, _)| key.owner)
+            .collect()
+    })
+}
+
+// Forgets the loans or LP positions of the pool changed by the transaction,
+// once its states have been rolled back or finalized
+pub fn forget(txid: Txid, pool_address: &String, kind: LedgerKind) {
+    for owner in touched(txid, pool_address, kind) {
+        crate::TX_LEDGERS.with_borrow_mut(|m| {
+            m.remove(&TxLedgerKey {
+                txid,
+                pool_address: pool_address.clone(),
+                kind,
+                owner,
+            })
+        });
+    }
+}
@@ -1,13 +1,28 @@
-use crate::{ExchangeError, pool::CoinMeta};
+use crate::{
+    ExchangeError,
+    interest::{BorrowIndex, PoolRates, RateModel},
+    loan::Loan,
+    pool::{CoinMeta, MinTxValueCurve, PoolParams},
+    roles::{ensure_admin, ensure_risk_manager},
+};
 use candid::{CandidType, Deserialize};
 use ic_cdk_macros::{query, update};
-use ree_types::{CoinBalance, CoinId, Utxo, bitcoin::Network, schnorr::request_ree_pool_address};
+use ree_types::{
+    CoinBalance, CoinId, TxRecord, Txid, Utxo, exchange_interfaces::NewBlockInfo,
+    schnorr::request_ree_pool_address,
+};
 use serde::Serialize;
 
+/// maximum number of entries returned by a single page of query_tx_records_page or query_blocks_page
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+/// maximum number of records read by a single call of query_tx_records_page
+pub const MAX_SCANNED_RECORDS: usize = 1000;
+
 // DepositOffer contains the return information for pre_deposit
 #[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
 pub struct DepositOffer {
-    pub pool_utxo: Option<Utxo>, // The current UTXO of the pool (None for first-time deposits)
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, which the deposit spends all or none of
     pub nonce: u64,
 }
 
@@ -22,18 +37,81 @@ pub fn pre_deposit(
         return Err(ExchangeError::TooSmallFunds);
     }
     let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    crate::pause::ensure_not_paused(&pool_address, "deposit")?;
     let state = pool.states.last().clone();
     Ok(DepositOffer {
-        pool_utxo: state.map(|s| s.utxo.clone()).flatten(),
+        pool_utxos: state.map(|s| s.utxos.clone()).unwrap_or_default(),
         nonce: state.map(|s| s.nonce).unwrap_or_default(),
     })
 }
 
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// WithdrawOffer contains information returned by pre_withdraw
+pub struct WithdrawOffer {
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, all spent by the transaction
+    pub nonce: u64,            // Transaction nonce to prevent replay attacks
+    pub output_btc: CoinBalance, // The amount of BTC the user will withdraw (may be less than requested amount if the pool has insufficient liquidity or the user insufficient shares)
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// LpPositionInfo describes the pool shares held by a depositor
+pub struct LpPositionInfo {
+    pub owner: String,
+    pub pool_address: String,
+    pub shares: u128,
+    pub total_shares: u128, // Shares held by all depositors of the pool
+    pub value: CoinBalance, // BTC the shares are currently worth, including earned interest
+}
+
+#[query]
+// get_lp_position returns the shares held by a depositor in a pool and their current value
+pub fn get_lp_position(
+    address: String,
+    pool_address: String,
+) -> Result<LpPositionInfo, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    let position = crate::get_lp_position(&pool_address, &address)
+        .ok_or(ExchangeError::NoLiquidityPosition)?;
+    let total_shares = crate::get_pool_shares(&pool_address);
+    let value = crate::lp::value_of_shares(position.shares(), total_shares, pool.pool_value())
+        .ok_or(ExchangeError::Overflow)?;
+    Ok(LpPositionInfo {
+        owner: position.owner.clone(),
+        pool_address: position.pool_address.clone(),
+        shares: position.shares(),
+        total_shares,
+        value: CoinBalance {
+            id: CoinId::btc(),
+            value: value as u128,
+        },
+    })
+}
+
+#[query]
+// pre_withdraw queries the information needed to build a withdrawal transaction
+// by specifying the target pool address, the depositor address and the amount of BTC to withdraw
+pub fn pre_withdraw(
+    pool_address: String,
+    depositor: String,
+    amount: CoinBalance,
+) -> Result<WithdrawOffer, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    let recent_state = pool.states.last().ok_or(ExchangeError::EmptyPool)?;
+    let position = crate::get_lp_position(&pool_address, &depositor)
+        .ok_or(ExchangeError::NoLiquidityPosition)?;
+    let (output_btc, _) = pool.available_to_withdraw(&position, amount)?;
+    Ok(WithdrawOffer {
+        nonce: recent_state.nonce,
+        pool_utxos: recent_state.utxos.clone(),
+        output_btc,
+    })
+}
+
 #[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
 // BorrowOffer contains information returned by pre_borrow
 pub struct BorrowOffer {
-    pub pool_utxo: Utxo,          // The current UTXO of the pool
-    pub nonce: u64,               // Transaction nonce to prevent replay attacks
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, all spent by the transaction
+    pub nonce: u64,            // Transaction nonce to prevent replay attacks
     pub input_runes: CoinBalance, // The collateral asset and amount the user needs to deposit
     pub output_btc: CoinBalance, // The amount of BTC the user will borrow (may be less than requested amount if the pool has insufficient BTC)
 }
@@ -43,37 +121,202 @@ pub struct BorrowOffer {
 // by specifying the target pool address and the amount requested to borrow
 pub fn pre_borrow(pool_address: String, amount: CoinBalance) -> Result<BorrowOffer, ExchangeError> {
     let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    crate::pause::ensure_not_paused(&pool_address, "borrow")?;
     let recent_state = pool.states.last().ok_or(ExchangeError::EmptyPool)?;
-    let (input_runes, output_btc) = pool.available_to_borrow(amount)?;
+    // Borrowing is refused while the collateral price is unknown or stale
+    let price = crate::oracle::collateral_price(&pool.base_id())?;
+    let (input_runes, output_btc) = pool.available_to_borrow(amount, &price)?;
     Ok(BorrowOffer {
         nonce: recent_state.nonce,
-        pool_utxo: recent_state.utxo.clone().expect("already checked"),
+        pool_utxos: recent_state.utxos.clone(),
         input_runes,
         output_btc,
     })
 }
 
-#[update]
-// init_pool creates a demonstration lending pool when the exchange is deployed
-// This pool allows users to borrow BTC satoshis at a 1:1 ratio by depositing RICH tokens as collateral
-async fn init_pool() -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if !ic_cdk::api::is_controller(&caller) {
-        return Err("Not authorized".to_string());
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// RepayOffer contains information returned by pre_repay
+pub struct RepayOffer {
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, all spent by the transaction
+    pub nonce: u64,            // Transaction nonce to prevent replay attacks
+    pub input_btc: CoinBalance, // The amount of BTC the user needs to repay (may be less than requested amount if the debt is smaller)
+    pub output_runes: CoinBalance, // The collateral released to the borrower
+}
+
+#[query]
+// pre_repay queries the information needed to build a repay transaction
+// by specifying the target pool address, the borrower (collateral owner) address and the amount of BTC to repay
+pub fn pre_repay(
+    pool_address: String,
+    borrower: String,
+    amount: CoinBalance,
+) -> Result<RepayOffer, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    crate::pause::ensure_not_paused(&pool_address, "repay")?;
+    let recent_state = pool.states.last().ok_or(ExchangeError::EmptyPool)?;
+    let loan = crate::get_loan(&pool_address, &borrower).ok_or(ExchangeError::NoOutstandingLoan)?;
+    let (input_btc, output_runes) = pool.available_to_repay(&loan, amount)?;
+    Ok(RepayOffer {
+        nonce: recent_state.nonce,
+        pool_utxos: recent_state.utxos.clone(),
+        input_btc,
+        output_runes,
+    })
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// LiquidateOffer contains information returned by pre_liquidate
+pub struct LiquidateOffer {
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, all spent by the transaction
+    pub nonce: u64,            // Transaction nonce to prevent replay attacks
+    pub input_btc: CoinBalance, // The amount of BTC the liquidator repays (may be less than requested amount if the debt is smaller)
+    pub output_runes: CoinBalance, // The collateral paid to the liquidator, including the liquidation bonus
+}
+
+#[query]
+// pre_liquidate queries the information needed to build a liquidation transaction
+// by specifying the target pool address, the address of the under-collateralized borrower
+// and the amount of BTC to repay on their behalf
+// The borrower address must be passed as the action_params of the liquidate intention
+pub fn pre_liquidate(
+    pool_address: String,
+    borrower: String,
+    amount: CoinBalance,
+) -> Result<LiquidateOffer, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    crate::pause::ensure_not_paused(&pool_address, "liquidate")?;
+    let recent_state = pool.states.last().ok_or(ExchangeError::EmptyPool)?;
+    let loan = crate::get_loan(&pool_address, &borrower).ok_or(ExchangeError::NoOutstandingLoan)?;
+    let price = crate::oracle::collateral_price(&pool.base_id())?;
+    let (input_btc, output_runes) = pool.available_to_liquidate(&loan, amount, &price)?;
+    Ok(LiquidateOffer {
+        nonce: recent_state.nonce,
+        pool_utxos: recent_state.utxos.clone(),
+        input_btc,
+        output_runes,
+    })
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// ConsolidateOffer contains information returned by pre_consolidate
+pub struct ConsolidateOffer {
+    pub pool_utxos: Vec<Utxo>, // The current UTXOs of the pool, all merged by the transaction
+    pub nonce: u64,            // Transaction nonce to prevent replay attacks
+    pub output_btc: CoinBalance, // The BTC held by the merged UTXO, the same as the pool's
+}
+
+#[query]
+// pre_consolidate queries the information needed to build a transaction merging the UTXOs of a pool
+// Deposits may add UTXOs to a pool, which are merged periodically so that they don't pile up
+// The consolidation leaves the pool's balances unchanged and its fee is paid by the submitter
+pub fn pre_consolidate(pool_address: String) -> Result<ConsolidateOffer, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    let recent_state = pool.states.last().ok_or(ExchangeError::EmptyPool)?;
+    (recent_state.utxos.len() > 1)
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidState(
+            "nothing to consolidate".to_string(),
+        ))?;
+    Ok(ConsolidateOffer {
+        pool_utxos: recent_state.utxos.clone(),
+        nonce: recent_state.nonce,
+        output_btc: CoinBalance {
+            id: CoinId::btc(),
+            value: recent_state.btc_supply()? as u128,
+        },
+    })
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// LoanInfo describes the current position of a borrower in a pool
+pub struct LoanInfo {
+    pub borrower: String,
+    pub pool_address: String,
+    pub collateral: CoinBalance, // Rune collateral locked in the pool
+    pub debt: CoinBalance,       // BTC owed by the borrower, including accrued interest
+}
+
+impl LoanInfo {
+    fn new(pool: &crate::Pool, loan: &Loan) -> Self {
+        Self {
+            borrower: loan.borrower.clone(),
+            pool_address: loan.pool_address.clone(),
+            collateral: CoinBalance {
+                id: pool.base_id(),
+                value: loan.collateral(),
+            },
+            debt: CoinBalance {
+                id: CoinId::btc(),
+                value: loan.debt(&pool.borrow_index) as u128,
+            },
+        }
     }
+}
+
+#[query]
+// get_loan returns the loans of a borrower (collateral owner) address across all pools
+pub fn get_loan(address: String) -> Vec<LoanInfo> {
+    crate::get_pools()
+        .iter()
+        .filter_map(|pool| {
+            crate::get_loan(&pool.addr, &address).map(|loan| LoanInfo::new(pool, &loan))
+        })
+        .collect()
+}
 
-    let id = CoinId::rune(72798, 1058);
-    let meta = CoinMeta {
-        id,
-        symbol: "HOPE•YOU•GET•RICH".to_string(),
-        min_amount: 1,
+#[query]
+// list_loans returns all loans of the specified pool
+pub fn list_loans(pool_address: String) -> Result<Vec<LoanInfo>, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    Ok(crate::get_pool_loans(&pool_address)
+        .iter()
+        .map(|loan| LoanInfo::new(&pool, loan))
+        .collect())
+}
+
+#[query]
+// get_rates returns the current utilization and interest rates of a pool
+pub fn get_rates(pool_address: String) -> Result<PoolRates, ExchangeError> {
+    let pool = crate::get_pool(&pool_address).ok_or(ExchangeError::InvalidPool)?;
+    Ok(pool.rates(crate::get_pool_debt(&pool)))
+}
+
+#[update(guard = "ensure_admin")]
+// create_pool registers a lending pool that lends BTC against the rune described by meta
+// Each rune can only back one pool, as the pool key is derived from the rune ID,
+// and symbols must be unique so that pools cannot be mistaken for one another
+// Returns the address of the new pool
+async fn create_pool(meta: CoinMeta, params: PoolParams) -> Result<String, String> {
+    if meta.id == CoinId::btc() {
+        return Err("BTC cannot be used as collateral".to_string());
+    }
+    if meta.min_amount == 0 {
+        return Err("min_amount must be positive".to_string());
+    }
+    if meta.symbol.trim().is_empty() {
+        return Err("symbol must not be empty".to_string());
+    }
+    params.validate()?;
+    let exists = |meta: &CoinMeta| {
+        crate::get_pools().iter().find_map(|p| {
+            if p.meta.id == meta.id {
+                Some(format!("Pool already exists for {}", meta.id))
+            } else if p.meta.symbol.eq_ignore_ascii_case(&meta.symbol) {
+                Some(format!("Pool already exists for symbol {}", meta.symbol))
+            } else {
+                None
+            }
+        })
     };
+    if let Some(e) = exists(&meta) {
+        return Err(e);
+    }
 
     // Request a pool address from the REE system
     let (untweaked, tweaked, addr) = request_ree_pool_address(
         crate::SCHNORR_KEY_NAME,
-        vec![id.to_string().as_bytes().to_vec()],
-        Network::Testnet4,
+        vec![meta.id.to_string().as_bytes().to_vec()],
+        crate::get_config().network.address_network(),
     )
     .await?;
 
@@ -84,76 +327,316 @@ async fn init_pool() -> Result<(), String> {
         tweaked,
         addr: addr.to_string(),
         states: vec![],
+        params,
+        borrow_index: BorrowIndex::default(),
     };
+    // Check again after the address request, as another call may have registered the pool meanwhile
+    if let Some(e) = exists(&pool.meta) {
+        return Err(e);
+    }
     // Store the pool in the LENDING_POOLS storage
     crate::LENDING_POOLS.with_borrow_mut(|p| {
         p.insert(addr.to_string(), pool);
     });
-    Ok(())
+    Ok(addr.to_string())
 }
 
-#[update]
+#[update(guard = "ensure_risk_manager")]
+// set_pool_ltv updates the loan-to-value ratio (in basis points) of a pool
+// It only applies to new borrows; existing loans keep the collateral they locked
+fn set_pool_ltv(pool_address: String, ltv_bps: u64) -> Result<(), String> {
+    update_pool_params(&pool_address, |params| params.ltv_bps = ltv_bps)
+}
+
+// Applies a change to the parameters of a pool, rejecting it if the resulting parameters are inconsistent
+// (e.g. an ltv above the liquidation threshold, which would let new loans be liquidated right away)
+fn update_pool_params(
+    pool_address: &String,
+    change: impl FnOnce(&mut PoolParams),
+) -> Result<(), String> {
+    crate::LENDING_POOLS.with_borrow_mut(|p| {
+        let mut pool = p
+            .get(pool_address)
+            .ok_or(format!("Pool not found: {}", pool_address))?;
+        change(&mut pool.params);
+        pool.params.validate()?;
+        p.insert(pool_address.clone(), pool);
+        Ok(())
+    })
+}
+
+#[update(guard = "ensure_risk_manager")]
+// set_pool_min_tx_value updates how the minimal deposit and borrow value of a pool grows with congestion
+fn set_pool_min_tx_value(
+    pool_address: String,
+    min_tx_value: MinTxValueCurve,
+) -> Result<(), String> {
+    update_pool_params(&pool_address, |params| params.min_tx_value = min_tx_value)
+}
+
+#[update(guard = "ensure_risk_manager")]
+// set_pool_rate_model updates the interest rate curve of a pool
+// Interest accrued before the change is kept; the new curve applies from the next block
+fn set_pool_rate_model(pool_address: String, rate_model: RateModel) -> Result<(), String> {
+    update_pool_params(&pool_address, |params| params.rate_model = rate_model)
+}
+
+#[update(guard = "ensure_risk_manager")]
+// set_pool_liquidation_params updates the liquidation threshold and bonus (in basis points) of a pool
+fn set_pool_liquidation_params(
+    pool_address: String,
+    threshold_bps: u64,
+    bonus_bps: u64,
+) -> Result<(), String> {
+    update_pool_params(&pool_address, |params| {
+        params.liquidation_threshold_bps = threshold_bps;
+        params.liquidation_bonus_bps = bonus_bps;
+    })
+}
+
+#[update(guard = "ensure_admin")]
 async fn reset_blocks() -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if !ic_cdk::api::is_controller(&caller) {
-        return Err("Not authorized".to_string());
-    }
     crate::BLOCKS.with_borrow_mut(|b| {
         b.clear_new();
     });
+    crate::BLOCK_GAPS.with_borrow_mut(|g| {
+        g.clear_new();
+    });
     Ok(())
 }
 
-#[update]
+#[update(guard = "ensure_admin")]
 async fn reset_tx_records() -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if !ic_cdk::api::is_controller(&caller) {
-        return Err("Not authorized".to_string());
-    }
     crate::TX_RECORDS.with_borrow_mut(|t| {
         t.clear_new();
     });
+    crate::TX_RECORD_COUNTS.with_borrow_mut(|t| {
+        t.clear_new();
+    });
+    crate::REORGED_TXIDS.with_borrow_mut(|t| {
+        t.clear_new();
+    });
+    crate::SIGNED_PSBTS.with_borrow_mut(|t| {
+        t.clear_new();
+    });
     Ok(())
 }
 
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// TxStateInfo describes the pool state created by a transaction
+pub struct TxStateInfo {
+    pool_address: String,
+    state_index: Option<u64>, // Position of the state in the pool's states chain (None if no longer present)
+    nonce: Option<u64>,
+    btc_supply: Option<u64>,   // BTC held by the pool after the transaction
+    rune_supply: Option<u128>, // Runes held by the pool after the transaction
+    btc_change: Option<i128>, // BTC moved by the transaction (None once the previous state is finalized)
+    rune_change: Option<i128>, // Runes moved by the transaction (None once the previous state is finalized)
+}
+
 #[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
 pub struct TxRecordInfo {
     txid: String,
     confirmed: bool,
     records: Vec<String>,
+    states: Vec<TxStateInfo>,
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+// TxRecordKey is the position of a record in TX_RECORDS, used as a pagination cursor
+pub struct TxRecordKey {
+    txid: Txid,
+    confirmed: bool,
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize, Default)]
+pub struct TxRecordFilter {
+    pool_address: Option<String>, // Only records affecting this pool
+    confirmed: Option<bool>,      // Only confirmed (true) or unconfirmed (false) records
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct TxRecordsPage {
+    records: Vec<TxRecordInfo>,
+    next: Option<TxRecordKey>, // Cursor of the next page, None on the last page
+}
+
+// Describes the state a transaction created in a pool, and the coins it moved
+fn tx_state_info(pool_address: &String, txid: Txid) -> TxStateInfo {
+    let pool = crate::get_pool(pool_address);
+    let index = pool
+        .as_ref()
+        .and_then(|p| p.states.iter().position(|s| s.id == Some(txid)));
+    let state = pool.as_ref().zip(index).map(|(p, i)| &p.states[i]);
+    let prev = pool
+        .as_ref()
+        .zip(index.and_then(|i| i.checked_sub(1)))
+        .map(|(p, i)| &p.states[i]);
+    let rune_id = pool.as_ref().map(|p| p.meta.id).unwrap_or(CoinId::btc());
+    let btc_supply = state.and_then(|s| s.btc_supply().ok());
+    let rune_supply = state.and_then(|s| s.rune_supply(rune_id).ok());
+    let prev_btc_supply = prev.and_then(|s| s.btc_supply().ok());
+    let prev_rune_supply = prev.and_then(|s| s.rune_supply(rune_id).ok());
+    TxStateInfo {
+        pool_address: pool_address.clone(),
+        state_index: index.map(|i| i as u64),
+        nonce: state.map(|s| s.nonce),
+        btc_supply,
+        rune_supply,
+        btc_change: btc_supply
+            .zip(prev_btc_supply)
+            .map(|(s, p)| s as i128 - p as i128),
+        rune_change: rune_supply
+            .zip(prev_rune_supply)
+            .map(|(s, p)| (s as i128).saturating_sub(p as i128)),
+    }
+}
+
+fn tx_record_info(txid: Txid, confirmed: bool, record: TxRecord) -> TxRecordInfo {
+    TxRecordInfo {
+        txid: txid.to_string(),
+        confirmed,
+        states: record
+            .pools
+            .iter()
+            .map(|pool_address| tx_state_info(pool_address, txid))
+            .collect(),
+        records: record.pools,
+    }
 }
 
 #[query]
+// Returns all transaction records
+// Kept for existing clients; query_tx_records_page bounds the size of the response
 pub fn query_tx_records() -> Result<Vec<TxRecordInfo>, String> {
     let res = crate::TX_RECORDS.with_borrow(|t| {
         t.iter()
-            .map(|((txid, confirmed), records)| TxRecordInfo {
-                txid: txid.to_string(),
-                confirmed,
-                records: records.pools.clone(),
-            })
+            .map(|((txid, confirmed), record)| tx_record_info(txid, confirmed, record))
             .collect()
     });
 
     Ok(res)
 }
 
+#[query]
+// Returns up to limit transaction records matching the filter, starting at the cursor
+// Records are indexed by txid only, so the filter is applied while scanning them in txid order:
+// a call reads at most MAX_SCANNED_RECORDS records, and a page of a selective filter may hold fewer
+// than limit records (even none) while next is still set. Callers follow next until it is None
+pub fn query_tx_records_page(
+    filter: TxRecordFilter,
+    cursor: Option<TxRecordKey>,
+    limit: u64,
+) -> Result<TxRecordsPage, String> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let mut entries = vec![];
+    let mut next = None;
+    crate::TX_RECORDS.with_borrow(|t| {
+        let iter = match cursor {
+            Some(cursor) => t.range((cursor.txid, cursor.confirmed)..),
+            None => t.range(..),
+        };
+        for (scanned, ((txid, confirmed), record)) in iter.enumerate() {
+            // Stop at the first record not visited, which starts the next page
+            if entries.len() == limit || scanned == MAX_SCANNED_RECORDS {
+                next = Some(TxRecordKey { txid, confirmed });
+                break;
+            }
+            let matches = filter.confirmed.is_none_or(|c| c == confirmed)
+                && filter
+                    .pool_address
+                    .as_ref()
+                    .is_none_or(|p| record.pools.contains(p));
+            if matches {
+                entries.push((txid, confirmed, record));
+            }
+        }
+    });
+    let records = entries
+        .into_iter()
+        .map(|(txid, confirmed, record)| tx_record_info(txid, confirmed, record))
+        .collect();
+
+    Ok(TxRecordsPage { records, next })
+}
+
 #[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
 pub struct BlockInfo {
     height: u32,
     hash: String,
+    timestamp: u64,
+    confirmed_txids: Vec<String>,
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct BlocksPage {
+    blocks: Vec<BlockInfo>,
+    next: Option<u32>, // Height to start the next page from, None on the last page
+}
+
+fn block_info(block: &NewBlockInfo) -> BlockInfo {
+    BlockInfo {
+        height: block.block_height,
+        hash: block.block_hash.clone(),
+        timestamp: block.block_timestamp,
+        confirmed_txids: block
+            .confirmed_txids
+            .iter()
+            .map(|txid| txid.to_string())
+            .collect(),
+    }
 }
 
 #[query]
+// Returns all the blocks not finalized yet
+// Kept for existing clients; query_blocks_page bounds the size of the response
 pub fn query_blocks() -> Result<Vec<BlockInfo>, String> {
-    let res = crate::BLOCKS.with_borrow(|b| {
-        b.iter()
-            .map(|(_, block)| BlockInfo {
-                height: block.block_height,
-                hash: block.block_hash.clone(),
-            })
-            .collect()
+    let res =
+        crate::BLOCKS.with_borrow(|b| b.iter().map(|(_, block)| block_info(&block)).collect());
+
+    Ok(res)
+}
+
+#[query]
+// Returns up to limit blocks with heights within [from_height, to_height]
+pub fn query_blocks_page(
+    from_height: Option<u32>,
+    to_height: Option<u32>,
+    limit: u64,
+) -> Result<BlocksPage, String> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let range = from_height.unwrap_or(0)..=to_height.unwrap_or(u32::MAX);
+    if range.is_empty() {
+        return Ok(BlocksPage {
+            blocks: vec![],
+            next: None,
+        });
+    }
+    let mut blocks = crate::BLOCKS.with_borrow(|b| {
+        b.range(range)
+            .take(limit + 1)
+            .map(|(_, block)| block_info(&block))
+            .collect::<Vec<_>>()
     });
+    let next = (blocks.len() > limit)
+        .then(|| blocks.pop())
+        .flatten()
+        .map(|block| block.height);
+
+    Ok(BlocksPage { blocks, next })
+}
+
+#[derive(Eq, PartialEq, CandidType, Clone, Debug, Deserialize, Serialize)]
+pub struct BlockGap {
+    from: u32,
+    to: u32,
+}
+
+#[query]
+// Returns the ranges of block heights skipped by the orchestrator notifications
+pub fn query_block_gaps() -> Result<Vec<BlockGap>, String> {
+    let res = crate::BLOCK_GAPS
+        .with_borrow(|g| g.iter().map(|(from, to)| BlockGap { from, to }).collect());
 
     Ok(res)
 }
@@ -1,31 +1,61 @@
+mod config;
+mod events;
 mod exchange;
+mod interest;
+mod ledger;
 mod lending;
+mod loan;
+mod lp;
+mod metrics;
+mod oracle;
+mod pause;
 mod pool;
+mod psbt;
 mod reorg;
+mod roles;
+mod runestone;
+mod schema;
 
-use crate::lending::{BorrowOffer, DepositOffer};
-use crate::pool::Pool;
-use candid::CandidType;
+use crate::config::{Config, Network};
+use crate::events::{Event, EventKind};
+use crate::interest::{PoolRates, RateModel};
+use crate::ledger::{LedgerKind, TxLedgerKey};
+use crate::lending::{
+    BorrowOffer, ConsolidateOffer, DepositOffer, LiquidateOffer, LoanInfo, LpPositionInfo,
+    RepayOffer, WithdrawOffer,
+};
+use crate::loan::{Loan, LoanKey};
+use crate::lp::{LpKey, LpPosition, LpState};
+use crate::metrics::{HttpRequest, HttpResponse};
+use crate::oracle::Price;
+use crate::pause::PauseFlags;
+use crate::pool::{CoinMeta, MinTxValueCurve, Pool, PoolParams, PoolTotals};
+use crate::roles::{Grants, Role};
+use candid::{CandidType, Principal};
+use ic_cdk_macros::{init, post_upgrade};
 use ic_stable_structures::{
-    DefaultMemoryImpl, StableBTreeMap,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, StableLog,
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
 };
-use lending::{BlockInfo, TxRecordInfo};
+use lending::{
+    BlockGap, BlockInfo, BlocksPage, TxRecordFilter, TxRecordInfo, TxRecordKey, TxRecordsPage,
+};
 use ree_types::{
-    CoinBalance, TxRecord, Txid,
+    CoinBalance, CoinId, TxRecord, Txid,
     exchange_interfaces::{
         ExecuteTxArgs, ExecuteTxResponse, GetMinimalTxValueArgs, GetMinimalTxValueResponse,
         GetPoolInfoArgs, GetPoolInfoResponse, GetPoolListResponse, NewBlockArgs, NewBlockInfo,
         NewBlockResponse, RollbackTxArgs, RollbackTxResponse,
     },
 };
+use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::HashSet;
 use thiserror::Error;
 
 const SCHNORR_KEY_NAME: &str = "key_1";
 
-#[derive(Debug, Error, CandidType)]
+#[derive(Debug, Error, CandidType, Serialize)]
 pub enum ExchangeError {
     #[error("overflow")]
     Overflow,
@@ -43,6 +73,44 @@ pub enum ExchangeError {
     InvalidSignPsbtArgs(String),
     #[error("pool state expired, current = {0}")]
     PoolStateExpired(u64),
+    #[error("no outstanding loan")]
+    NoOutstandingLoan,
+    #[error("collateral price unavailable")]
+    PriceUnavailable,
+    #[error("collateral price is stale, updated at = {0}")]
+    PriceStale(u64),
+    #[error("loan is healthy and cannot be liquidated")]
+    LoanHealthy,
+    #[error("no liquidity position")]
+    NoLiquidityPosition,
+    #[error("action paused: {0}")]
+    Paused(String),
+}
+
+// ExecuteTxError describes why execute_tx rejected a transaction
+// It is returned serialized as JSON, so the orchestrator can tell failures apart
+#[derive(Debug, Error, Serialize)]
+pub enum ExecuteTxError {
+    #[error("InvalidPsbt: {0}")]
+    InvalidPsbt(String),
+    #[error("InvalidIntentionIndex: {0} is out of the intention set")]
+    InvalidIntentionIndex(u32),
+    #[error("GroupNotExecuted: the first intention of the exchange has not been executed")]
+    GroupNotExecuted,
+    #[error("DuplicatePool: a transaction can change each pool only once")]
+    DuplicatePool,
+    #[error("UnknownPool: {0}")]
+    UnknownPool(String),
+    #[error("PoolExecuting: {0}")]
+    PoolExecuting(String),
+    #[error("StateChanged: {0} changed while the transaction was signed")]
+    StateChanged(String),
+    #[error("InvalidAction: {0}")]
+    InvalidAction(String),
+    #[error("ValidationFailed: {0}")]
+    ValidationFailed(#[from] ExchangeError),
+    #[error("SigningFailed: {0}")]
+    SigningFailed(String),
 }
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
@@ -78,9 +146,155 @@ thread_local! {
       )
   );
 
+  // LOANS stores the position of every borrower
+  // Key: LoanKey (pool_address, borrower address)
+  static LOANS: RefCell<StableBTreeMap<LoanKey, Loan, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
+      )
+  );
+
+  // PRICES stores the latest price of each collateral coin pushed by the controllers
+  // Key: CoinId string
+  static PRICES: RefCell<StableBTreeMap<String, Price, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+      )
+  );
+
+  // LP_POSITIONS stores the pool shares owned by every depositor
+  // Key: LpKey (pool_address, depositor address)
+  static LP_POSITIONS: RefCell<StableBTreeMap<LpKey, LpPosition, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))),
+      )
+  );
+
+  // REORGED_TXIDS tracks the transactions unconfirmed by a reorg
+  // Key: Txid, Value: height of the reorged block that contained the transaction
+  // A transaction leaves this set when it is confirmed again in the replacement chain, however late,
+  // or when the orchestrator rolls it back because it was dropped or conflicted
+  // Its pool states are kept meanwhile: the finality of the height it was reorged from says nothing
+  // about whether the transaction is still in the mempool
+  // Note: This storage will be moved to the SDK in the future
+  static REORGED_TXIDS: RefCell<StableBTreeMap<Txid, u32, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))),
+      )
+  );
+
+  // BLOCK_GAPS records the ranges of block heights the exchange has not been notified of
+  // Key: first missing height, Value: last missing height
+  // Note: This storage will be moved to the SDK in the future
+  static BLOCK_GAPS: RefCell<StableBTreeMap<u32, u32, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))),
+      )
+  );
+
+  // CONFIG stores the deployment settings of the exchange, set by the init argument
+  static CONFIG: RefCell<StableCell<Config, Memory>> = RefCell::new(
+      StableCell::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))),
+          Config::default(),
+      )
+      .expect("failed to init config")
+  );
+
+  // EVENTS is the append-only log of pool state transitions, indexed by sequence number
+  static EVENTS: RefCell<StableLog<Event, Memory, Memory>> = RefCell::new(
+      StableLog::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))),
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))),
+      )
+      .expect("failed to init events")
+  );
+
+  // PAUSE_FLAGS stores the actions halted by the controllers
+  // Key: pool address, or pause::ALL_POOLS for the actions halted in every pool
+  static PAUSE_FLAGS: RefCell<StableBTreeMap<String, PauseFlags, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))),
+      )
+  );
+
+  // ROLES stores the operator roles granted by the admins
+  // Key: principal (text), Value: the roles granted to it
+  // Controllers hold every role without being listed here
+  static ROLES: RefCell<StableBTreeMap<String, Grants, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))),
+      )
+  );
+
+  // TX_LEDGERS indexes the loans and LP positions changed by each transaction
+  // Key: TxLedgerKey (txid, pool_address, kind, owner)
+  // Rollbacks and finalizations look up the positions to update here instead of scanning the pool
+  // Note: states created before the index existed are only cleaned up by later finalizations
+  static TX_LEDGERS: RefCell<StableBTreeMap<TxLedgerKey, (), Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+      )
+  );
+
+  // POOL_TOTALS keeps running totals of the loans and LP positions of each pool
+  // Key: pool address
+  // Spares the full scans of the pool's positions when computing its debt and shares
+  static POOL_TOTALS: RefCell<StableBTreeMap<String, PoolTotals, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))),
+      )
+  );
+
+  // SIGNED_PSBTS keeps the PSBT signed for each transaction until it is finalized or rolled back
+  // Key: txid, Value: the signed PSBT in hex
+  // Returned to the orchestrator when it submits the later intentions of the exchange
+  static SIGNED_PSBTS: RefCell<StableBTreeMap<Txid, String, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))),
+      )
+  );
+
+  // TX_RECORD_COUNTS keeps running counts of the transaction records
+  // Key: true for confirmed records, false for unconfirmed records
+  // Spares the full scan of TX_RECORDS when the metrics are collected
+  static TX_RECORD_COUNTS: RefCell<StableBTreeMap<bool, u64, Memory>> = RefCell::new(
+      StableBTreeMap::init(
+          MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))),
+      )
+  );
+
   pub static EXECUTING_POOLS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
 }
 
+#[init]
+// Stores the deployment settings provided when the canister is installed
+fn init(config: Config) {
+    config.validate().expect("invalid config");
+    CONFIG.with_borrow_mut(|c| {
+        c.set(config).expect("failed to save config");
+    });
+}
+
+#[post_upgrade]
+// Migrates the data in stable memory to the layouts of the new code
+// The deployment settings are replaced when an upgrade argument is provided
+// No pre_upgrade hook is needed, as all the state already lives in stable memory
+fn post_upgrade(config: Option<Config>) {
+    schema::migrate_all();
+    seed_protocol_shares();
+    if let Some(config) = config {
+        config.validate().expect("invalid config");
+        CONFIG.with_borrow_mut(|c| {
+            c.set(config).expect("failed to save config");
+        });
+    }
+}
+
+pub(crate) fn get_config() -> Config {
+    CONFIG.with_borrow(|c| c.get().clone())
+}
+
 pub(crate) fn get_pools() -> Vec<Pool> {
     LENDING_POOLS.with_borrow(|p| p.iter().map(|p| p.1.clone()).collect::<Vec<_>>())
 }
@@ -89,6 +303,326 @@ pub(crate) fn get_pool(addr: &String) -> Option<Pool> {
     LENDING_POOLS.with_borrow(|p| p.get(addr))
 }
 
+pub(crate) fn get_loan(pool_address: &String, borrower: &String) -> Option<Loan> {
+    LOANS.with_borrow(|l| l.get(&LoanKey::new(pool_address, borrower)))
+}
+
+pub(crate) fn get_pool_loans(pool_address: &String) -> Vec<Loan> {
+    LOANS.with_borrow(|l| {
+        l.range(LoanKey::first_of(pool_address)..)
+            .take_while(|(key, _)| &key.pool_address == pool_address)
+            .map(|(_, loan)| loan)
+            .collect()
+    })
+}
+
+// Returns the BTC owed by all borrowers of the pool, including accrued interest
+// Saturates at u64::MAX like Loan::debt
+pub(crate) fn get_pool_debt(pool: &Pool) -> u64 {
+    pool.borrow_index
+        .to_debt(get_pool_totals(&pool.addr).scaled_debt)
+        .unwrap_or(u64::MAX)
+}
+
+// Returns the running totals of the latest states of the pool's loans and LP positions
+// Pools whose totals were never recorded (created before they were kept) are summed from their positions
+pub(crate) fn get_pool_totals(pool_address: &String) -> PoolTotals {
+    POOL_TOTALS
+        .with_borrow(|t| t.get(pool_address))
+        .unwrap_or_else(|| PoolTotals {
+            scaled_debt: get_pool_loans(pool_address)
+                .iter()
+                .fold(0u128, |total, loan| {
+                    total.saturating_add(loan.scaled_debt())
+                }),
+            shares: get_pool_lp_positions(pool_address)
+                .iter()
+                .fold(0u128, |total, position| {
+                    total.saturating_add(position.shares())
+                }),
+        })
+}
+
+// Applies a change to the running totals of a pool
+// Must be called before the positions are written, as missing totals are summed from them
+fn update_pool_totals(pool_address: &String, change: impl FnOnce(&mut PoolTotals)) {
+    let mut totals = get_pool_totals(pool_address);
+    change(&mut totals);
+    POOL_TOTALS.with_borrow_mut(|t| t.insert(pool_address.clone(), totals));
+}
+
+// Stores a loan, or removes it if loan is None, keeping the pool's total debt in step
+fn write_loan(key: LoanKey, loan: Option<Loan>) {
+    let before = get_loan(&key.pool_address, &key.borrower)
+        .map(|loan| loan.scaled_debt())
+        .unwrap_or_default();
+    let after = loan
+        .as_ref()
+        .map(|loan| loan.scaled_debt())
+        .unwrap_or_default();
+    update_pool_totals(&key.pool_address, |totals| {
+        totals.scaled_debt = totals
+            .scaled_debt
+            .saturating_sub(before)
+            .saturating_add(after);
+    });
+    LOANS.with_borrow_mut(|l| match loan {
+        Some(loan) => l.insert(key, loan),
+        None => l.remove(&key),
+    });
+}
+
+pub(crate) fn save_loan(loan: Loan) {
+    if let Some(txid) = loan.states.last().and_then(|s| s.id) {
+        ledger::index(txid, &loan.pool_address, LedgerKind::Loan, &loan.borrower);
+    }
+    write_loan(loan.key(), Some(loan));
+}
+
+// Returns the loans of the pool changed by the specified transaction
+fn get_loans_touched_by(pool_address: &String, txid: Txid) -> Vec<Loan> {
+    ledger::touched(txid, pool_address, LedgerKind::Loan)
+        .iter()
+        .filter_map(|borrower| get_loan(pool_address, borrower))
+        .filter(|loan| loan.touched_by(txid))
+        .collect()
+}
+
+// Rolls back every loan of the pool that was changed by the specified transaction
+pub(crate) fn rollback_loans(pool_address: &String, txid: Txid) {
+    let loans = get_loans_touched_by(pool_address, txid);
+    ledger::forget(txid, pool_address, LedgerKind::Loan);
+    for mut loan in loans {
+        if let Err(e) = loan.rollback(txid) {
+            ic_cdk::println!("Loan rollback failed: {:?}", e);
+            continue;
+        }
+        let key = loan.key();
+        write_loan(key, (!loan.states.is_empty()).then_some(loan));
+    }
+}
+
+// Finalizes every loan of the pool that was changed by the specified transaction
+// Loans that have been fully repaid are removed once their last state is finalized
+pub(crate) fn finalize_loans(pool_address: &String, txid: Txid) {
+    let loans = get_loans_touched_by(pool_address, txid);
+    ledger::forget(txid, pool_address, LedgerKind::Loan);
+    for mut loan in loans {
+        if let Err(e) = loan.finalize(txid) {
+            ic_cdk::println!("Loan finalize failed: {:?}", e);
+            continue;
+        }
+        let key = loan.key();
+        write_loan(key, (!loan.is_closed()).then_some(loan));
+    }
+}
+
+pub(crate) fn get_lp_position(pool_address: &String, owner: &String) -> Option<LpPosition> {
+    LP_POSITIONS.with_borrow(|l| l.get(&LpKey::new(pool_address, owner)))
+}
+
+pub(crate) fn get_pool_lp_positions(pool_address: &String) -> Vec<LpPosition> {
+    LP_POSITIONS.with_borrow(|l| {
+        l.range(LpKey::first_of(pool_address)..)
+            .take_while(|(key, _)| &key.pool_address == pool_address)
+            .map(|(_, position)| position)
+            .collect()
+    })
+}
+
+// Returns the total shares of the pool held by all depositors
+pub(crate) fn get_pool_shares(pool_address: &String) -> u128 {
+    get_pool_totals(pool_address).shares
+}
+
+// Stores an LP position, or removes it if position is None, keeping the pool's total shares in step
+fn write_lp_position(key: LpKey, position: Option<LpPosition>) {
+    let before = get_lp_position(&key.pool_address, &key.owner)
+        .map(|position| position.shares())
+        .unwrap_or_default();
+    let after = position
+        .as_ref()
+        .map(|position| position.shares())
+        .unwrap_or_default();
+    update_pool_totals(&key.pool_address, |totals| {
+        totals.shares = totals.shares.saturating_sub(before).saturating_add(after);
+    });
+    LP_POSITIONS.with_borrow_mut(|l| match position {
+        Some(position) => l.insert(key, position),
+        None => l.remove(&key),
+    });
+}
+
+// Mints shares for the BTC held by pools that have no depositor, such as pools funded before
+// share accounting existed, so that their first depositor cannot claim it
+// The shares are owned by the pool address, which can never initiate a withdrawal
+fn seed_protocol_shares() {
+    for pool in get_pools() {
+        let has_positions = LP_POSITIONS.with_borrow(|l| {
+            l.range(LpKey::first_of(&pool.addr)..)
+                .next()
+                .is_some_and(|(key, _)| key.pool_address == pool.addr)
+        });
+        let value = pool.pool_value();
+        if has_positions || value == 0 {
+            continue;
+        }
+        let mut position = LpPosition::new(&pool.addr, &pool.addr);
+        position.commit(LpState {
+            id: None,
+            shares: value as u128,
+        });
+        save_lp_position(position);
+    }
+}
+
+pub(crate) fn save_lp_position(position: LpPosition) {
+    if let Some(txid) = position.states.last().and_then(|s| s.id) {
+        ledger::index(
+            txid,
+            &position.pool_address,
+            LedgerKind::LpPosition,
+            &position.owner,
+        );
+    }
+    write_lp_position(position.key(), Some(position));
+}
+
+// Returns the LP positions of the pool changed by the specified transaction
+fn get_lp_positions_touched_by(pool_address: &String, txid: Txid) -> Vec<LpPosition> {
+    ledger::touched(txid, pool_address, LedgerKind::LpPosition)
+        .iter()
+        .filter_map(|owner| get_lp_position(pool_address, owner))
+        .filter(|position| position.touched_by(txid))
+        .collect()
+}
+
+// Rolls back every LP position of the pool that was changed by the specified transaction
+pub(crate) fn rollback_lp_positions(pool_address: &String, txid: Txid) {
+    let positions = get_lp_positions_touched_by(pool_address, txid);
+    ledger::forget(txid, pool_address, LedgerKind::LpPosition);
+    for mut position in positions {
+        if let Err(e) = position.rollback(txid) {
+            ic_cdk::println!("LP position rollback failed: {:?}", e);
+            continue;
+        }
+        let key = position.key();
+        write_lp_position(key, (!position.states.is_empty()).then_some(position));
+    }
+}
+
+// Finalizes every LP position of the pool that was changed by the specified transaction
+// Positions whose shares have all been burned are removed once their last state is finalized
+pub(crate) fn finalize_lp_positions(pool_address: &String, txid: Txid) {
+    let positions = get_lp_positions_touched_by(pool_address, txid);
+    ledger::forget(txid, pool_address, LedgerKind::LpPosition);
+    for mut position in positions {
+        if let Err(e) = position.finalize(txid) {
+            ic_cdk::println!("LP position finalize failed: {:?}", e);
+            continue;
+        }
+        let key = position.key();
+        write_lp_position(key, (!position.is_closed()).then_some(position));
+    }
+}
+
+// Rolls back the specified pools to their state before the transaction
+// The loans and LP positions changed by the removed pool states are rolled back as well
+// Returns the transactions whose states were removed: txid and the later transactions built on it
+pub(crate) fn rollback_pools(pool_addresses: &Vec<String>, txid: Txid) -> Vec<Txid> {
+    let mut removed_txids = vec![];
+    pool_addresses.iter().for_each(|pool_address| {
+        LENDING_POOLS.with_borrow_mut(|pools| {
+            if let Some(mut pool) = pools.get(pool_address) {
+                match pool.rollback(txid) {
+                    Ok(removed) => {
+                        pools.insert(pool_address.clone(), pool);
+                        removed.iter().for_each(|txid| {
+                            rollback_loans(pool_address, *txid);
+                            rollback_lp_positions(pool_address, *txid);
+                        });
+                        removed_txids.extend(removed);
+                    }
+                    Err(e) => ic_cdk::println!("Rollback failed: {:?}", e),
+                }
+            } else {
+                ic_cdk::println!("Pool not found: {}", pool_address);
+            }
+        });
+    });
+    removed_txids
+}
+
+// Returns the number of confirmed or unconfirmed transaction records
+// Counts never recorded (records kept before they were counted) are counted from the records
+pub(crate) fn get_tx_record_count(confirmed: bool) -> u64 {
+    TX_RECORD_COUNTS
+        .with_borrow(|c| c.get(&confirmed))
+        .unwrap_or_else(|| {
+            TX_RECORDS.with_borrow(|t| {
+                t.iter()
+                    .filter(|((_, is_confirmed), _)| *is_confirmed == confirmed)
+                    .count() as u64
+            })
+        })
+}
+
+// Stores the record of a transaction, or removes it if record is None, keeping the record counts in step
+// Returns the record previously stored under the key
+pub(crate) fn write_tx_record(key: (Txid, bool), record: Option<TxRecord>) -> Option<TxRecord> {
+    let existed = TX_RECORDS.with_borrow(|t| t.contains_key(&key));
+    if existed != record.is_some() {
+        let count = get_tx_record_count(key.1);
+        let count = match record {
+            Some(_) => count.saturating_add(1),
+            None => count.saturating_sub(1),
+        };
+        TX_RECORD_COUNTS.with_borrow_mut(|c| c.insert(key.1, count));
+    }
+    TX_RECORDS.with_borrow_mut(|t| match record {
+        Some(record) => t.insert(key, record),
+        None => t.remove(&key),
+    })
+}
+
+// Removes every record of a transaction that is rolled back
+// Returns its record, if it was still known
+fn forget_tx(txid: Txid) -> Option<TxRecord> {
+    let unconfirmed = write_tx_record((txid, false), None);
+    let confirmed = write_tx_record((txid, true), None);
+    REORGED_TXIDS.with_borrow_mut(|m| m.remove(&txid));
+    SIGNED_PSBTS.with_borrow_mut(|m| m.remove(&txid));
+    confirmed.or(unconfirmed)
+}
+
+// Rolls back a transaction along with every transaction built on the states it created
+// A dependent spends the pool UTXOs of an earlier transaction, so it can no longer be mined either:
+// its states in all its pools, its records and its loan and LP states are removed as well,
+// and an event of the specified kind is recorded for each pool of each removed transaction
+// Returns false if no record of the transaction was found
+pub(crate) fn rollback_with_dependents(
+    txid: Txid,
+    kind: EventKind,
+    block_height: Option<u32>,
+) -> bool {
+    let Some(record) = forget_tx(txid) else {
+        return false;
+    };
+    let mut pending = vec![(txid, record.pools)];
+    while let Some((txid, pools)) = pending.pop() {
+        ic_cdk::println!("rollback txid: {} with pools: {:?}", txid, pools);
+        let removed = rollback_pools(&pools, txid);
+        events::record_for_pools(kind, txid, &pools, block_height);
+        for dependent in removed.into_iter().filter(|removed| *removed != txid) {
+            // Records are removed when a dependent is queued, so it is only queued once
+            if let Some(record) = forget_tx(dependent) {
+                pending.push((dependent, record.pools));
+            }
+        }
+    }
+    true
+}
+
 #[must_use]
 pub struct ExecuteTxGuard(String);
 
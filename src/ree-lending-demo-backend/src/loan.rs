@@ -0,0 +1,239 @@
+use crate::ExchangeError;
+use crate::interest::BorrowIndex;
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::Txid;
+use serde::Serialize;
+
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+// LoanKey identifies a loan position in the LOANS storage
+// Keys are ordered by pool first so all loans of a pool can be scanned with a range query
+pub struct LoanKey {
+    pub pool_address: String,
+    pub borrower: String, // Address that owns the collateral (InputCoin::from of the borrow)
+}
+
+impl LoanKey {
+    pub fn new(pool_address: &String, borrower: &String) -> Self {
+        Self {
+            pool_address: pool_address.clone(),
+            borrower: borrower.clone(),
+        }
+    }
+
+    // The smallest possible key of a pool, used as the start of a range query
+    pub fn first_of(pool_address: &String) -> Self {
+        Self::new(pool_address, &String::new())
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, Default)]
+// LoanState represents the position of a borrower after a transaction
+// Like PoolState, a new LoanState is appended to the Loan's states chain after each transaction
+pub struct LoanState {
+    pub id: Option<Txid>, // Transaction ID that created this state
+    pub collateral: u128, // Rune collateral locked in the pool UTXO
+    #[serde(alias = "debt")]
+    pub scaled_debt: u128, // BTC owed by the borrower divided by the pool's borrow index
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
+// Loan records how much a borrower owes to a pool and how much collateral backs it
+// The states chain follows the pool's states so that loans can be rolled back and finalized
+pub struct Loan {
+    pub borrower: String,
+    pub pool_address: String,
+    pub states: Vec<LoanState>, // Chain of historical loan states
+}
+
+impl Storable for LoanKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let mut bytes = vec![];
+        let _ = ciborium::ser::into_writer(self, &mut bytes);
+        std::borrow::Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode LoanKey");
+        dire
+    }
+}
+
+impl Versioned for Loan {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for Loan {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Loan");
+        dire
+    }
+}
+
+impl Loan {
+    pub fn new(pool_address: &String, borrower: &String) -> Self {
+        Self {
+            borrower: borrower.clone(),
+            pool_address: pool_address.clone(),
+            states: vec![],
+        }
+    }
+
+    pub fn key(&self) -> LoanKey {
+        LoanKey::new(&self.pool_address, &self.borrower)
+    }
+
+    pub fn collateral(&self) -> u128 {
+        self.states.last().map(|s| s.collateral).unwrap_or_default()
+    }
+
+    pub fn scaled_debt(&self) -> u128 {
+        self.states
+            .last()
+            .map(|s| s.scaled_debt)
+            .unwrap_or_default()
+    }
+
+    // Returns the BTC currently owed, principal plus accrued interest
+    // Saturates at u64::MAX, which can never be repaid by a single transaction
+    pub fn debt(&self, index: &BorrowIndex) -> u64 {
+        index.to_debt(self.scaled_debt()).unwrap_or(u64::MAX)
+    }
+
+    // Returns true if the specified transaction created one of the states of this loan
+    pub fn touched_by(&self, txid: Txid) -> bool {
+        self.states.iter().any(|state| state.id == Some(txid))
+    }
+
+    // Returns true if the loan has been fully repaid and no pending state refers to it
+    pub fn is_closed(&self) -> bool {
+        self.states.len() <= 1 && self.scaled_debt() == 0 && self.collateral() == 0
+    }
+
+    // Rollback the loan state to before the specified transaction
+    // Removes the state created by txid and all subsequent states
+    pub(crate) fn rollback(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+        let idx = self
+            .states
+            .iter()
+            .position(|state| state.id == Some(txid))
+            .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
+        self.states.truncate(idx);
+        Ok(())
+    }
+
+    // Finalize a transaction by making its state the new base state
+    // Removes all states before the specified transaction
+    pub(crate) fn finalize(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+        let idx = self
+            .states
+            .iter()
+            .position(|state| state.id == Some(txid))
+            .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
+        self.states.drain(..idx);
+        Ok(())
+    }
+
+    // Returns the balances of the loan after `repaid` BTC of its debt is paid back and `released` collateral
+    // leaves the pool; the caller sets the id of the transaction
+    // Repaying the whole debt clears it, whatever the rounding of the borrow index
+    // With write_off, a debt left without any collateral is written off as bad debt:
+    // nothing backs it any more, so the loss is borne by the depositors through the pool value
+    pub(crate) fn paid_down(
+        &self,
+        repaid: u64,
+        released: u128,
+        index: &BorrowIndex,
+        write_off: bool,
+    ) -> Result<LoanState, ExchangeError> {
+        let collateral = self
+            .collateral()
+            .checked_sub(released)
+            .ok_or(ExchangeError::Overflow)?;
+        let scaled_debt = if repaid == self.debt(index) || (write_off && collateral == 0) {
+            0
+        } else {
+            index
+                .to_scaled_down(repaid)
+                .map(|scaled| self.scaled_debt().saturating_sub(scaled))
+                .ok_or(ExchangeError::Overflow)?
+        };
+        Ok(LoanState {
+            id: None,
+            collateral,
+            scaled_debt,
+        })
+    }
+
+    // Adds a new LoanState to the chain after a transaction is executed
+    pub(crate) fn commit(&mut self, state: LoanState) {
+        self.states.push(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interest::INDEX_SCALE;
+
+    // A loan of 1000 satoshis backed by 500 units of collateral, after the index has doubled
+    fn loan() -> (Loan, BorrowIndex) {
+        let index = BorrowIndex {
+            value: 2 * INDEX_SCALE,
+            height: 1,
+        };
+        let mut loan = Loan::new(&"pool".to_string(), &"borrower".to_string());
+        loan.commit(LoanState {
+            id: None,
+            collateral: 500,
+            scaled_debt: index.to_scaled(1000).unwrap(),
+        });
+        (loan, index)
+    }
+
+    #[test]
+    fn partial_payments_reduce_the_debt() {
+        let (loan, index) = loan();
+        assert_eq!(loan.debt(&index), 1000);
+        let state = loan.paid_down(400, 200, &index, false).unwrap();
+        assert_eq!(state.collateral, 300);
+        let mut paid = loan.clone();
+        paid.commit(state);
+        assert_eq!(paid.debt(&index), 600);
+    }
+
+    #[test]
+    fn paying_the_whole_debt_clears_it() {
+        let (loan, index) = loan();
+        let state = loan.paid_down(1000, 500, &index, false).unwrap();
+        assert_eq!((state.collateral, state.scaled_debt), (0, 0));
+    }
+
+    #[test]
+    fn debt_left_without_collateral_is_written_off_only_on_request() {
+        let (loan, index) = loan();
+        let state = loan.paid_down(400, 500, &index, true).unwrap();
+        assert_eq!((state.collateral, state.scaled_debt), (0, 0));
+        let state = loan.paid_down(400, 500, &index, false).unwrap();
+        assert_eq!(state.collateral, 0);
+        assert_ne!(state.scaled_debt, 0);
+        // Collateral left keeps the rest of the debt due
+        let state = loan.paid_down(400, 499, &index, true).unwrap();
+        assert_ne!(state.scaled_debt, 0);
+    }
+
+    #[test]
+    fn releasing_more_than_the_collateral_fails() {
+        let (loan, index) = loan();
+        assert!(loan.paid_down(1000, 501, &index, false).is_err());
+    }
+}
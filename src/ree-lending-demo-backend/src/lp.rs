@@ -0,0 +1,200 @@
+use crate::ExchangeError;
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::Txid;
+use serde::Serialize;
+
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+// LpKey identifies a liquidity provider position in the LP_POSITIONS storage
+// Keys are ordered by pool first so all positions of a pool can be scanned with a range query
+pub struct LpKey {
+    pub pool_address: String,
+    pub owner: String, // Address that deposited the BTC (InputCoin::from of the deposit)
+}
+
+impl LpKey {
+    pub fn new(pool_address: &String, owner: &String) -> Self {
+        Self {
+            pool_address: pool_address.clone(),
+            owner: owner.clone(),
+        }
+    }
+
+    // The smallest possible key of a pool, used as the start of a range query
+    pub fn first_of(pool_address: &String) -> Self {
+        Self::new(pool_address, &String::new())
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, Default)]
+// LpState represents the shares held by a depositor after a transaction
+pub struct LpState {
+    pub id: Option<Txid>, // Transaction ID that created this state
+    pub shares: u128,     // Shares of the pool value owned by the depositor
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
+// LpPosition records the pool shares minted to a depositor
+// Shares entitle their owner to a pro rata part of the pool value, including the interest paid by borrowers
+pub struct LpPosition {
+    pub owner: String,
+    pub pool_address: String,
+    pub states: Vec<LpState>, // Chain of historical position states
+}
+
+impl Storable for LpKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let mut bytes = vec![];
+        let _ = ciborium::ser::into_writer(self, &mut bytes);
+        std::borrow::Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode LpKey");
+        dire
+    }
+}
+
+impl Versioned for LpPosition {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for LpPosition {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode LpPosition");
+        dire
+    }
+}
+
+impl LpPosition {
+    pub fn new(pool_address: &String, owner: &String) -> Self {
+        Self {
+            owner: owner.clone(),
+            pool_address: pool_address.clone(),
+            states: vec![],
+        }
+    }
+
+    pub fn key(&self) -> LpKey {
+        LpKey::new(&self.pool_address, &self.owner)
+    }
+
+    pub fn shares(&self) -> u128 {
+        self.states.last().map(|s| s.shares).unwrap_or_default()
+    }
+
+    // Returns true if the specified transaction created one of the states of this position
+    pub fn touched_by(&self, txid: Txid) -> bool {
+        self.states.iter().any(|state| state.id == Some(txid))
+    }
+
+    // Returns true if all shares have been burned and no pending state refers to the position
+    pub fn is_closed(&self) -> bool {
+        self.states.len() <= 1 && self.shares() == 0
+    }
+
+    // Rollback the position to before the specified transaction
+    // Removes the state created by txid and all subsequent states
+    pub(crate) fn rollback(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+        let idx = self
+            .states
+            .iter()
+            .position(|state| state.id == Some(txid))
+            .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
+        self.states.truncate(idx);
+        Ok(())
+    }
+
+    // Finalize a transaction by making its state the new base state
+    // Removes all states before the specified transaction
+    pub(crate) fn finalize(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+        let idx = self
+            .states
+            .iter()
+            .position(|state| state.id == Some(txid))
+            .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
+        self.states.drain(..idx);
+        Ok(())
+    }
+
+    // Adds a new LpState to the chain after a transaction is executed
+    pub(crate) fn commit(&mut self, state: LpState) {
+        self.states.push(state);
+    }
+}
+
+// Converts a BTC amount into pool shares, rounded down in favor of the pool
+// The first deposit into a pool without shares mints one share per satoshi
+// Pools holding BTC without shares are seeded with protocol-owned shares on upgrade,
+// so a first depositor never receives BTC it did not deposit
+pub fn shares_for(sats: u64, total_shares: u128, pool_value: u64) -> Option<u128> {
+    if total_shares == 0 || pool_value == 0 {
+        return Some(sats as u128);
+    }
+    (sats as u128)
+        .checked_mul(total_shares)
+        .map(|v| v / pool_value as u128)
+}
+
+// Converts a BTC amount into the shares burned to withdraw it, rounded up in favor of the pool
+pub fn shares_to_burn(sats: u64, total_shares: u128, pool_value: u64) -> Option<u128> {
+    (pool_value != 0)
+        .then(|| (sats as u128).checked_mul(total_shares))
+        .flatten()
+        .map(|v| v.div_ceil(pool_value as u128))
+}
+
+// Converts pool shares into the BTC they are worth, rounded down in favor of the pool
+pub fn value_of_shares(shares: u128, total_shares: u128, pool_value: u64) -> Option<u64> {
+    if total_shares == 0 {
+        return Some(0);
+    }
+    shares
+        .checked_mul(pool_value as u128)
+        .map(|v| v / total_shares)
+        .and_then(|v| v.try_into().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_deposit_mints_one_share_per_satoshi() {
+        assert_eq!(shares_for(10000, 0, 0), Some(10000));
+    }
+
+    #[test]
+    fn deposits_mint_shares_in_proportion_to_the_pool_value() {
+        // 10000 shares worth 20000 satoshis after interest was paid
+        assert_eq!(shares_for(5000, 10000, 20000), Some(2500));
+        assert_eq!(shares_for(3, 10000, 20000), Some(1));
+        assert_eq!(value_of_shares(2500, 12500, 25000), Some(5000));
+        assert_eq!(shares_for(u64::MAX, u128::MAX, 1), None);
+    }
+
+    #[test]
+    fn withdrawals_burn_shares_rounded_up() {
+        assert_eq!(shares_to_burn(5000, 10000, 20000), Some(2500));
+        assert_eq!(shares_to_burn(3, 10000, 20000), Some(2));
+        assert_eq!(shares_to_burn(1, 10000, 0), None);
+    }
+
+    #[test]
+    fn round_trips_never_create_value() {
+        let (total_shares, pool_value) = (10000u128, 30001u64);
+        let minted = shares_for(7, total_shares, pool_value).unwrap();
+        let value = value_of_shares(minted, total_shares + minted, pool_value + 7).unwrap();
+        assert!(value <= 7);
+        assert_eq!(value_of_shares(1, 0, pool_value), Some(0));
+    }
+}
@@ -0,0 +1,236 @@
+use candid::{CandidType, Deserialize};
+use ic_cdk_macros::query;
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Clone, CandidType, Debug, Deserialize)]
+// HttpRequest is the request passed by the HTTP gateway to http_request
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize)]
+// HttpResponse is the response returned to the HTTP gateway
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn ok(content_type: &str, body: String) -> Self {
+        Self {
+            status_code: 200,
+            headers: vec![
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("Content-Length".to_string(), body.len().to_string()),
+            ],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self {
+            status_code: 404,
+            headers: vec![],
+            body: b"Not found".to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+// PoolMetrics describes the latest state of a pool
+struct PoolMetrics {
+    address: String,
+    symbol: String,
+    btc_reserved: u64,
+    rune_reserved: u128,
+    nonce: u64,
+    states: u64, // Number of unfinalized pool states, growing while finalization is stuck
+    utxos: u64,  // Number of UTXOs held by the pool, growing with deposits until consolidated
+    borrowed: u64,
+}
+
+#[derive(Serialize)]
+// Metrics is the snapshot of the exchange exposed to monitoring
+struct Metrics {
+    pools: Vec<PoolMetrics>,
+    confirmed_tx_records: u64,
+    unconfirmed_tx_records: u64,
+    blocks: u64,
+    tip_height: Option<u32>,
+    block_gaps: u64,
+    reorged_txids: u64,
+    cycles: u128,
+}
+
+fn collect_metrics() -> Metrics {
+    let pools = crate::get_pools()
+        .iter()
+        .map(|p| {
+            let state = p.states.last();
+            PoolMetrics {
+                address: p.addr.clone(),
+                symbol: p.meta.symbol.clone(),
+                btc_reserved: state.and_then(|s| s.btc_supply().ok()).unwrap_or_default(),
+                rune_reserved: state
+                    .and_then(|s| s.rune_supply(p.meta.id).ok())
+                    .unwrap_or_default(),
+                nonce: state.map(|s| s.nonce).unwrap_or_default(),
+                states: p.states.len() as u64,
+                utxos: state.map(|s| s.utxos.len() as u64).unwrap_or_default(),
+                borrowed: crate::get_pool_debt(p),
+            }
+        })
+        .collect();
+    Metrics {
+        pools,
+        confirmed_tx_records: crate::get_tx_record_count(true),
+        unconfirmed_tx_records: crate::get_tx_record_count(false),
+        blocks: crate::BLOCKS.with_borrow(|b| b.len()),
+        tip_height: crate::BLOCKS.with_borrow(|b| b.iter().rev().next().map(|(height, _)| height)),
+        block_gaps: crate::BLOCK_GAPS.with_borrow(|g| g.len()),
+        reorged_txids: crate::REORGED_TXIDS.with_borrow(|r| r.len()),
+        cycles: ic_cdk::api::canister_balance128(),
+    }
+}
+
+// Escapes a Prometheus label value: backslashes, double quotes and line feeds must be escaped,
+// as pool symbols are chosen when pools are created
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Renders the metrics in the Prometheus text exposition format
+fn encode_prometheus(metrics: &Metrics) -> String {
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let per_pool = |value: &dyn Fn(&PoolMetrics) -> String| {
+        metrics
+            .pools
+            .iter()
+            .map(|p| {
+                (
+                    format!(
+                        "{{pool=\"{}\",symbol=\"{}\"}}",
+                        escape_label(&p.address),
+                        escape_label(&p.symbol)
+                    ),
+                    value(p),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let global = |value: String| vec![(String::new(), value)];
+
+    gauge(
+        "pool_btc_reserved",
+        "BTC (in satoshis) held by the pool UTXOs.",
+        per_pool(&|p| p.btc_reserved.to_string()),
+    );
+    gauge(
+        "pool_rune_reserved",
+        "Runes held by the pool UTXOs.",
+        per_pool(&|p| p.rune_reserved.to_string()),
+    );
+    gauge(
+        "pool_nonce",
+        "Nonce of the latest pool state.",
+        per_pool(&|p| p.nonce.to_string()),
+    );
+    gauge(
+        "pool_states",
+        "Number of unfinalized pool states.",
+        per_pool(&|p| p.states.to_string()),
+    );
+    gauge(
+        "pool_utxos",
+        "Number of UTXOs held by the pool.",
+        per_pool(&|p| p.utxos.to_string()),
+    );
+    gauge(
+        "pool_borrowed",
+        "BTC (in satoshis) owed by the pool's borrowers.",
+        per_pool(&|p| p.borrowed.to_string()),
+    );
+    gauge(
+        "tx_records_confirmed",
+        "Confirmed transactions awaiting finalization.",
+        global(metrics.confirmed_tx_records.to_string()),
+    );
+    gauge(
+        "tx_records_unconfirmed",
+        "Transactions awaiting confirmation.",
+        global(metrics.unconfirmed_tx_records.to_string()),
+    );
+    gauge(
+        "blocks",
+        "Blocks kept for reorg detection.",
+        global(metrics.blocks.to_string()),
+    );
+    gauge(
+        "blocks_tip_height",
+        "Height of the latest block received.",
+        metrics
+            .tip_height
+            .map(|height| global(height.to_string()))
+            .unwrap_or_default(),
+    );
+    gauge(
+        "block_gaps",
+        "Ranges of block heights never received.",
+        global(metrics.block_gaps.to_string()),
+    );
+    gauge(
+        "reorged_txids",
+        "Reorged transactions awaiting confirmation.",
+        global(metrics.reorged_txids.to_string()),
+    );
+    gauge(
+        "cycles_balance",
+        "Cycles balance of the canister.",
+        global(metrics.cycles.to_string()),
+    );
+    out
+}
+
+#[query]
+// http_request serves the exchange metrics to monitoring systems
+// GET /metrics returns the Prometheus text format, GET /metrics.json the same metrics as JSON
+fn http_request(req: HttpRequest) -> HttpResponse {
+    let path = req.url.split('?').next().unwrap_or_default();
+    match (req.method.as_str(), path) {
+        ("GET", "/metrics") => HttpResponse::ok(
+            "text/plain; version=0.0.4",
+            encode_prometheus(&collect_metrics()),
+        ),
+        ("GET", "/metrics.json") => HttpResponse::ok(
+            "application/json",
+            serde_json::to_string(&collect_metrics()).unwrap_or_default(),
+        ),
+        _ => HttpResponse::not_found(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("RICH"), "RICH");
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd".to_string());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::ExchangeError;
+use crate::roles::ensure_oracle_feeder;
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize};
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::{Storable, storable::Bound};
+use ree_types::CoinId;
+use serde::Serialize;
+
+/// prices are quoted in satoshis per PRICE_SCALE units of the rune
+pub const PRICE_SCALE: u128 = 100_000_000;
+
+/// prices older than one hour (in nanoseconds) are considered stale
+pub const MAX_PRICE_AGE: u64 = 3_600_000_000_000;
+
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// Price is the value of a rune in satoshis at a given time
+pub struct Price {
+    pub value: u128,     // Satoshis per PRICE_SCALE units of the rune
+    pub updated_at: u64, // Time (in nanoseconds) the price was published
+}
+
+impl Price {
+    // Converts an amount of the rune into its value in satoshis, rounded down
+    pub fn value_of(&self, amount: u128) -> Option<u128> {
+        amount.checked_mul(self.value).map(|v| v / PRICE_SCALE)
+    }
+
+    // Converts a value in satoshis into the amount of the rune worth it, rounded up
+    pub fn amount_for(&self, sats: u128) -> Option<u128> {
+        (self.value != 0)
+            .then(|| sats.checked_mul(PRICE_SCALE))
+            .flatten()
+            .map(|v| v.div_ceil(self.value))
+    }
+
+    // Converts a value in satoshis into the amount of the rune worth it, rounded down
+    pub fn amount_worth(&self, sats: u128) -> Option<u128> {
+        (self.value != 0)
+            .then(|| sats.checked_mul(PRICE_SCALE))
+            .flatten()
+            .map(|v| v / self.value)
+    }
+}
+
+impl Versioned for Price {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for Price {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Price");
+        dire
+    }
+}
+
+// PriceSource provides the latest known price of a coin
+// Implementations may be fed by the controllers, by other canisters or by HTTPS outcalls
+pub trait PriceSource {
+    fn latest_price(&self, coin: &CoinId) -> Option<Price>;
+}
+
+// LocalPriceSource serves the prices pushed into the canister and stored in PRICES
+pub struct LocalPriceSource;
+
+impl LocalPriceSource {
+    // Records the price of a coin at the specified time
+    pub fn set_price(&self, coin: &CoinId, value: u128, now: u64) {
+        crate::PRICES.with_borrow_mut(|p| {
+            p.insert(
+                coin.to_string(),
+                Price {
+                    value,
+                    updated_at: now,
+                },
+            );
+        });
+    }
+}
+
+impl PriceSource for LocalPriceSource {
+    fn latest_price(&self, coin: &CoinId) -> Option<Price> {
+        crate::PRICES.with_borrow(|p| p.get(&coin.to_string()))
+    }
+}
+
+// Returns the latest price of a coin from the source
+// Fails if no price is known or if it is older than MAX_PRICE_AGE at the time `now`
+pub fn fresh_price(
+    source: &impl PriceSource,
+    coin: &CoinId,
+    now: u64,
+) -> Result<Price, ExchangeError> {
+    let price = source
+        .latest_price(coin)
+        .ok_or(ExchangeError::PriceUnavailable)?;
+    (price.value != 0)
+        .then(|| ())
+        .ok_or(ExchangeError::PriceUnavailable)?;
+    (now.saturating_sub(price.updated_at) <= MAX_PRICE_AGE)
+        .then(|| ())
+        .ok_or(ExchangeError::PriceStale(price.updated_at))?;
+    Ok(price)
+}
+
+// Returns the current price of a collateral coin from the in-canister price feed
+pub fn collateral_price(coin: &CoinId) -> Result<Price, ExchangeError> {
+    fresh_price(&LocalPriceSource, coin, ic_cdk::api::time())
+}
+
+#[update(guard = "ensure_oracle_feeder")]
+// set_price publishes the price of a rune in satoshis per PRICE_SCALE units
+fn set_price(coin: CoinId, value: u128) -> Result<(), String> {
+    if value == 0 {
+        return Err("price must be positive".to_string());
+    }
+    LocalPriceSource.set_price(&coin, value, ic_cdk::api::time());
+    Ok(())
+}
+
+#[query]
+// get_price returns the latest published price of a rune, whether stale or not
+fn get_price(coin: CoinId) -> Option<Price> {
+    LocalPriceSource.latest_price(&coin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coin() -> CoinId {
+        CoinId {
+            block: 840000,
+            tx: 1,
+        }
+    }
+
+    struct FixedPriceSource(Option<Price>);
+
+    impl PriceSource for FixedPriceSource {
+        fn latest_price(&self, _coin: &CoinId) -> Option<Price> {
+            self.0.clone()
+        }
+    }
+
+    fn price(value: u128, updated_at: u64) -> FixedPriceSource {
+        FixedPriceSource(Some(Price { value, updated_at }))
+    }
+
+    #[test]
+    fn fresh_price_accepts_prices_up_to_max_age() {
+        let now = 10 * MAX_PRICE_AGE;
+        let source = price(1000, now - MAX_PRICE_AGE);
+        assert_eq!(fresh_price(&source, &coin(), now).unwrap().value, 1000);
+        // Prices published after `now` (clock skew between calls) are fresh
+        let source = price(1000, now + 1);
+        assert!(fresh_price(&source, &coin(), now).is_ok());
+    }
+
+    #[test]
+    fn fresh_price_rejects_stale_prices() {
+        let now = 10 * MAX_PRICE_AGE;
+        let source = price(1000, now - MAX_PRICE_AGE - 1);
+        assert!(matches!(
+            fresh_price(&source, &coin(), now),
+            Err(ExchangeError::PriceStale(t)) if t == now - MAX_PRICE_AGE - 1
+        ));
+    }
+
+    #[test]
+    fn fresh_price_rejects_missing_and_zero_prices() {
+        assert!(matches!(
+            fresh_price(&FixedPriceSource(None), &coin(), 0),
+            Err(ExchangeError::PriceUnavailable)
+        ));
+        assert!(matches!(
+            fresh_price(&price(0, 0), &coin(), 0),
+            Err(ExchangeError::PriceUnavailable)
+        ));
+    }
+
+    #[test]
+    fn local_price_source_serves_the_latest_price() {
+        let other = CoinId {
+            block: 840000,
+            tx: 2,
+        };
+        LocalPriceSource.set_price(&coin(), 1000, 1);
+        LocalPriceSource.set_price(&coin(), 2000, 2);
+        assert_eq!(
+            LocalPriceSource.latest_price(&coin()),
+            Some(Price {
+                value: 2000,
+                updated_at: 2,
+            })
+        );
+        assert_eq!(LocalPriceSource.latest_price(&other), None);
+        assert_eq!(
+            fresh_price(&LocalPriceSource, &coin(), 2 + MAX_PRICE_AGE)
+                .unwrap()
+                .value,
+            2000
+        );
+        assert!(fresh_price(&LocalPriceSource, &coin(), 3 + MAX_PRICE_AGE).is_err());
+    }
+
+    #[test]
+    fn price_conversions_round_in_favor_of_the_pool() {
+        // 1 unit of the rune is worth 1.5 satoshis
+        let price = Price {
+            value: 3 * PRICE_SCALE / 2,
+            updated_at: 0,
+        };
+        assert_eq!(price.value_of(3), Some(4));
+        assert_eq!(price.amount_for(4), Some(3));
+        assert_eq!(price.amount_for(5), Some(4));
+        assert_eq!(price.amount_worth(5), Some(3));
+        assert_eq!(price.value_of(u128::MAX), None);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::ExchangeError;
+use crate::roles::ensure_pauser;
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize};
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::{Storable, storable::Bound};
+use serde::Serialize;
+
+/// key of the flags applying to all pools in PAUSE_FLAGS
+pub const ALL_POOLS: &str = "*";
+
+#[derive(Clone, Copy, CandidType, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+// PauseFlags tells which actions are halted
+// Repays and liquidations have their own flags, so they stay enabled while borrows are paused
+// Withdrawals and consolidations are never paused, so depositors can always leave a pool
+pub struct PauseFlags {
+    pub deposit: bool,
+    pub borrow: bool,
+    pub repay: bool,
+    pub liquidate: bool,
+}
+
+impl PauseFlags {
+    // Returns true if the action (as named in intentions) is paused
+    pub fn is_paused(&self, action: &str) -> bool {
+        match action {
+            "deposit" => self.deposit,
+            "borrow" => self.borrow,
+            "repay" => self.repay,
+            "liquidate" => self.liquidate,
+            _ => false,
+        }
+    }
+
+    // Returns the flags set in either self or other
+    pub fn union(&self, other: &PauseFlags) -> PauseFlags {
+        PauseFlags {
+            deposit: self.deposit || other.deposit,
+            borrow: self.borrow || other.borrow,
+            repay: self.repay || other.repay,
+            liquidate: self.liquidate || other.liquidate,
+        }
+    }
+
+    // Returns the flags of self that are not set in other
+    pub fn difference(&self, other: &PauseFlags) -> PauseFlags {
+        PauseFlags {
+            deposit: self.deposit && !other.deposit,
+            borrow: self.borrow && !other.borrow,
+            repay: self.repay && !other.repay,
+            liquidate: self.liquidate && !other.liquidate,
+        }
+    }
+}
+
+impl Versioned for PauseFlags {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for PauseFlags {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode PauseFlags");
+        dire
+    }
+}
+
+fn stored_flags(key: &str) -> PauseFlags {
+    crate::PAUSE_FLAGS
+        .with_borrow(|p| p.get(&key.to_string()))
+        .unwrap_or_default()
+}
+
+// Returns the flags in force for a pool: its own flags combined with the global ones
+pub fn effective_flags(pool_address: &String) -> PauseFlags {
+    stored_flags(pool_address).union(&stored_flags(ALL_POOLS))
+}
+
+// Fails if the action is paused in the pool or globally
+pub fn ensure_not_paused(pool_address: &String, action: &str) -> Result<(), ExchangeError> {
+    (!effective_flags(pool_address).is_paused(action))
+        .then(|| ())
+        .ok_or(ExchangeError::Paused(action.to_string()))
+}
+
+// Applies a change to the flags stored under key, removing them once nothing is paused
+fn update_flags(key: String, change: impl FnOnce(PauseFlags) -> PauseFlags) {
+    crate::PAUSE_FLAGS.with_borrow_mut(|p| {
+        let flags = change(p.get(&key).unwrap_or_default());
+        if flags == PauseFlags::default() {
+            p.remove(&key);
+        } else {
+            p.insert(key, flags);
+        }
+    });
+}
+
+#[update(guard = "ensure_pauser")]
+// pause_pool halts the actions set in flags for a pool, leaving the other flags unchanged
+fn pause_pool(pool_address: String, flags: PauseFlags) -> Result<(), String> {
+    crate::get_pool(&pool_address).ok_or(format!("Pool not found: {}", pool_address))?;
+    update_flags(pool_address, |current| current.union(&flags));
+    Ok(())
+}
+
+#[update(guard = "ensure_pauser")]
+// unpause_pool resumes the actions set in flags for a pool
+// Actions paused for all pools stay halted until unpause_all is called
+fn unpause_pool(pool_address: String, flags: PauseFlags) -> Result<(), String> {
+    update_flags(pool_address, |current| current.difference(&flags));
+    Ok(())
+}
+
+#[update(guard = "ensure_pauser")]
+// pause_all is the kill switch halting the actions set in flags in every pool
+fn pause_all(flags: PauseFlags) -> Result<(), String> {
+    update_flags(ALL_POOLS.to_string(), |current| current.union(&flags));
+    Ok(())
+}
+
+#[update(guard = "ensure_pauser")]
+// unpause_all resumes the actions set in flags that were paused for all pools
+fn unpause_all(flags: PauseFlags) -> Result<(), String> {
+    update_flags(ALL_POOLS.to_string(), |current| current.difference(&flags));
+    Ok(())
+}
+
+#[query]
+// get_pause_flags returns the actions halted in a pool, whether paused for the pool or globally
+// Without a pool address, it returns the actions paused for all pools
+fn get_pause_flags(pool_address: Option<String>) -> PauseFlags {
+    match pool_address {
+        Some(pool_address) => effective_flags(&pool_address),
+        None => stored_flags(ALL_POOLS),
+    }
+}
@@ -1,4 +1,9 @@
 use crate::ExchangeError;
+use crate::interest::{BorrowIndex, PoolRates, RateModel};
+use crate::loan::{Loan, LoanState};
+use crate::lp::{self, LpPosition, LpState};
+use crate::oracle::Price;
+use crate::schema::{self, Versioned};
 use candid::{CandidType, Deserialize};
 use ic_stable_structures::{Storable, storable::Bound};
 use ree_types::{CoinBalance, CoinBalances, CoinId, InputCoin, OutputCoin, Pubkey, Txid, Utxo};
@@ -7,6 +12,18 @@ use serde::Serialize;
 /// each tx's satoshis should be >= 10000
 pub const MIN_BTC_VALUE: u64 = 10000;
 
+/// maximum number of UTXOs a pool can hold before deposits must merge them
+pub const MAX_POOL_UTXOS: usize = 16;
+
+/// ratios are expressed in basis points, 10000 = 100%
+pub const MAX_BPS: u64 = 10000;
+
+/// highest increase of the minimal tx value per queued transaction, 100000 = 10x base
+pub const MAX_MIN_TX_VALUE_STEP_BPS: u64 = 100_000;
+
+/// highest minimal tx value a pool can require, 1 BTC
+pub const MAX_MIN_TX_VALUE: u64 = 100_000_000;
+
 #[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct CoinMeta {
     pub id: CoinId,
@@ -24,6 +41,124 @@ impl CoinMeta {
     }
 }
 
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+// PoolParams holds the risk parameters of a pool, adjustable by the controllers
+pub struct PoolParams {
+    pub ltv_bps: u64, // Loan-to-value ratio: the BTC that can be borrowed per satoshi of collateral value
+    pub rate_model: RateModel, // Annual interest rate charged on outstanding loans, by utilization
+    pub liquidation_threshold_bps: u64, // Debt to collateral value ratio above which a loan can be liquidated
+    pub liquidation_bonus_bps: u64, // Extra collateral value granted to liquidators on top of the repaid debt
+    pub min_tx_value: MinTxValueCurve, // Minimal BTC value of deposits and borrows, by mempool congestion
+}
+
+#[derive(Clone, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// MinTxValueCurve raises the minimal transaction value as unconfirmed transactions queue up,
+// making it more expensive to spam a pool with a long chain of small transactions
+pub struct MinTxValueCurve {
+    pub base: u64,     // Minimal value (in satoshis) when no transaction is queued
+    pub step_bps: u64, // Increase of the minimal value per queued transaction, relative to base
+    pub cap: u64,      // Upper bound of the minimal value
+}
+
+impl Default for MinTxValueCurve {
+    fn default() -> Self {
+        Self {
+            base: MIN_BTC_VALUE,
+            step_bps: 0,
+            cap: MIN_BTC_VALUE,
+        }
+    }
+}
+
+impl MinTxValueCurve {
+    // Returns the minimal transaction value for the number of unconfirmed transactions in the pool's queue
+    // Saturates instead of overflowing, so curves stored before validation existed still stop at cap
+    pub fn value_at(&self, zero_confirmed_tx_queue_length: u32) -> u64 {
+        let increase = (self.base as u128)
+            .saturating_mul(self.step_bps as u128)
+            .saturating_mul(zero_confirmed_tx_queue_length as u128)
+            / MAX_BPS as u128;
+        (self.base as u128)
+            .saturating_add(increase)
+            .min(self.cap.max(self.base) as u128) as u64
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.base == 0 {
+            return Err("base must be positive".to_string());
+        }
+        if self.cap < self.base {
+            return Err("cap must not be lower than base".to_string());
+        }
+        if self.cap > MAX_MIN_TX_VALUE {
+            return Err(format!("cap must not exceed {}", MAX_MIN_TX_VALUE));
+        }
+        if self.step_bps > MAX_MIN_TX_VALUE_STEP_BPS {
+            return Err(format!(
+                "step_bps must not exceed {}",
+                MAX_MIN_TX_VALUE_STEP_BPS
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl PoolParams {
+    // Verifies the parameters are consistent before they are applied to a pool
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ltv_bps == 0 || self.ltv_bps > MAX_BPS {
+            return Err(format!("ltv_bps must be within (0, {}]", MAX_BPS));
+        }
+        if self.liquidation_threshold_bps < self.ltv_bps || self.liquidation_threshold_bps > MAX_BPS
+        {
+            return Err(format!(
+                "liquidation_threshold_bps must be within [ltv_bps, {}]",
+                MAX_BPS
+            ));
+        }
+        if self.liquidation_bonus_bps > MAX_BPS {
+            return Err(format!("liquidation_bonus_bps must not exceed {}", MAX_BPS));
+        }
+        self.rate_model.validate()?;
+        self.min_tx_value.validate()
+    }
+
+    // Returns true if the debt exceeds the collateral value (both in satoshis) weighted by the liquidation threshold
+    pub fn is_liquidatable(
+        &self,
+        debt: u64,
+        collateral_value: u128,
+    ) -> Result<bool, ExchangeError> {
+        let max_debt = collateral_value
+            .checked_mul(self.liquidation_threshold_bps as u128)
+            .ok_or(ExchangeError::Overflow)?
+            / MAX_BPS as u128;
+        Ok(debt != 0 && debt as u128 > max_debt)
+    }
+
+    // Returns the collateral value (in satoshis) paid to a liquidator repaying the specified debt:
+    // the repaid value plus the liquidation bonus, rounded down
+    pub fn liquidation_reward(&self, repaid: u64) -> Result<u128, ExchangeError> {
+        Ok((repaid as u128)
+            .checked_mul((MAX_BPS + self.liquidation_bonus_bps) as u128)
+            .ok_or(ExchangeError::Overflow)?
+            / MAX_BPS as u128)
+    }
+}
+
+impl Default for PoolParams {
+    fn default() -> Self {
+        Self {
+            ltv_bps: MAX_BPS,
+            rate_model: RateModel::default(),
+            liquidation_threshold_bps: MAX_BPS,
+            liquidation_bonus_bps: 500,
+            min_tx_value: MinTxValueCurve::default(),
+        }
+    }
+}
+
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize)]
 // Pool represents the basic structure of a lending pool
 // It maintains the pool's state history, metadata, and address information
@@ -33,33 +168,165 @@ pub struct Pool {
     pub pubkey: Pubkey,
     pub tweaked: Pubkey,
     pub addr: String, // Pool address (cached to avoid re-acquisition costs)
+    #[serde(default)]
+    pub params: PoolParams, // Risk parameters (pools created before they existed use the defaults)
+    #[serde(default)]
+    pub borrow_index: BorrowIndex, // Interest accumulated on loans, accrued per Bitcoin block
 }
 
 impl Pool {
-    pub fn attrs(&self) -> String {
-        "".to_string()
+    // Returns the pool attributes exposed in PoolInfo as a JSON string
+    // borrowed is the BTC currently owed by the pool's borrowers
+    // total_shares is the number of shares held by all depositors
+    pub fn attrs(&self, borrowed: u64, total_shares: u128) -> String {
+        serde_json::to_string(&PoolAttributes {
+            rates: self.rates(borrowed),
+            total_shares,
+        })
+        .unwrap_or_default()
+    }
+
+    // Returns the minimal BTC value of a deposit or borrow given the pool's unconfirmed transaction queue
+    pub fn minimal_tx_value(&self, zero_confirmed_tx_queue_length: u32) -> u64 {
+        self.params
+            .min_tx_value
+            .value_at(zero_confirmed_tx_queue_length)
+    }
+
+    // Returns the value owed to the depositors: the BTC held by the pool plus the BTC owed by borrowers
+    pub fn pool_value(&self) -> u64 {
+        self.states
+            .last()
+            .and_then(|s| s.btc_supply().ok())
+            .unwrap_or_default()
+            .saturating_add(crate::get_pool_debt(self))
+    }
+
+    // Calculates the current interest rates from the pool's BTC supply and outstanding borrows
+    pub fn rates(&self, borrowed: u64) -> PoolRates {
+        let available = self
+            .states
+            .last()
+            .and_then(|s| s.btc_supply().ok())
+            .unwrap_or_default();
+        let utilization_bps = crate::interest::utilization_bps(available, borrowed);
+        PoolRates {
+            utilization_bps,
+            borrow_rate_bps: self.params.rate_model.borrow_rate(utilization_bps),
+            supply_rate_bps: self.params.rate_model.supply_rate(utilization_bps),
+            total_borrowed: borrowed,
+        }
     }
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+// PoolTotals sums the latest states of all the loans and LP positions of a pool
+pub struct PoolTotals {
+    pub scaled_debt: u128, // Debt owed by all borrowers divided by the pool's borrow index
+    pub shares: u128,      // Shares held by all depositors
+}
+
+impl Versioned for PoolTotals {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for PoolTotals {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode PoolTotals");
+        dire
+    }
+}
+
+#[derive(Serialize)]
+// PoolAttributes is the JSON content of PoolInfo::attributes
+struct PoolAttributes {
+    #[serde(flatten)]
+    rates: PoolRates,
+    total_shares: u128,
+}
+
 #[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, Default)]
 // PoolState represents the state of a pool
 // A new PoolState is created and added to the Pool's states chain after each transaction
+// Holding several UTXOs lets a deposit add its own UTXO instead of spending the pool's, which keeps
+// deposits small and cheap; every transaction still builds on the latest state (nonce), so the
+// transactions of a pool are still applied one after another
 pub struct PoolState {
     pub id: Option<Txid>, // Transaction ID that created this state (None for initial state)
     pub nonce: u64,       // Incremental counter to prevent replay attacks
-    pub utxo: Option<Utxo>, // The UTXO holding the pool's assets
+    pub utxos: Vec<Utxo>, // The UTXOs holding the pool's assets
 }
 
 impl PoolState {
-    pub fn btc_supply(&self) -> u64 {
-        self.utxo.as_ref().map(|utxo| utxo.sats).unwrap_or_default()
+    pub fn btc_supply(&self) -> Result<u64, ExchangeError> {
+        self.utxos.iter().try_fold(0u64, |sats, utxo| {
+            sats.checked_add(utxo.sats).ok_or(ExchangeError::Overflow)
+        })
     }
 
-    pub fn rune_supply(&self, rune_id: CoinId) -> u128 {
-        self.utxo
-            .as_ref()
-            .map(|utxo| utxo.coins.value_of(&rune_id))
-            .unwrap_or_default()
+    pub fn rune_supply(&self, rune_id: CoinId) -> Result<u128, ExchangeError> {
+        self.utxos.iter().try_fold(0u128, |runes, utxo| {
+            runes
+                .checked_add(utxo.coins.value_of(&rune_id))
+                .ok_or(ExchangeError::Overflow)
+        })
+    }
+
+    // Verifies pool_utxo_spent lists all the UTXOs of the state, or none of them if allowed
+    // Returns the UTXOs spent by the transaction
+    pub(crate) fn spent_utxos(
+        &self,
+        pool_utxo_spent: &Vec<String>,
+        allow_none: bool,
+    ) -> Result<Vec<Utxo>, ExchangeError> {
+        if allow_none && pool_utxo_spent.is_empty() {
+            return Ok(vec![]);
+        }
+        (pool_utxo_spent.len() == self.utxos.len()
+            && self
+                .utxos
+                .iter()
+                .all(|utxo| pool_utxo_spent.contains(&utxo.outpoint())))
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidSignPsbtArgs(
+            "pool_utxo_spent/pool state mismatch".to_string(),
+        ))?;
+        Ok(self.utxos.clone())
+    }
+}
+
+#[derive(Deserialize)]
+// PoolStateV1 is the layout of PoolState before a pool could hold several UTXOs
+struct PoolStateV1 {
+    id: Option<Txid>,
+    nonce: u64,
+    utxo: Option<Utxo>,
+}
+
+impl From<PoolStateV1> for PoolState {
+    fn from(state: PoolStateV1) -> Self {
+        Self {
+            id: state.id,
+            nonce: state.nonce,
+            utxos: state.utxo.into_iter().collect(),
+        }
+    }
+}
+
+impl Versioned for PoolState {
+    const VERSION: u32 = 2;
+
+    // Versions 0 and 1 held a single optional UTXO
+    fn migrate(version: u32, data: ciborium::Value) -> Result<Self, String> {
+        data.deserialized::<PoolStateV1>()
+            .map(PoolState::from)
+            .map_err(|e| format!("failed to migrate from version {}: {}", version, e))
     }
 }
 
@@ -67,28 +334,58 @@ impl Storable for PoolState {
     const BOUND: Bound = Bound::Unbounded;
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        let mut bytes = vec![];
-        let _ = ciborium::ser::into_writer(self, &mut bytes);
-        std::borrow::Cow::Owned(bytes)
+        std::borrow::Cow::Owned(schema::encode(self))
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        let dire = ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode PoolState");
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode PoolState");
         dire
     }
 }
 
+#[derive(Deserialize)]
+// PoolV1 is the layout of Pool before a pool could hold several UTXOs
+struct PoolV1 {
+    states: Vec<PoolStateV1>,
+    meta: CoinMeta,
+    pubkey: Pubkey,
+    tweaked: Pubkey,
+    addr: String,
+    #[serde(default)]
+    params: PoolParams,
+    #[serde(default)]
+    borrow_index: BorrowIndex,
+}
+
+impl Versioned for Pool {
+    const VERSION: u32 = 2;
+
+    // Versions 0 and 1 stored states holding a single optional UTXO
+    fn migrate(version: u32, data: ciborium::Value) -> Result<Self, String> {
+        let pool: PoolV1 = data
+            .deserialized()
+            .map_err(|e| format!("failed to migrate from version {}: {}", version, e))?;
+        Ok(Self {
+            states: pool.states.into_iter().map(PoolState::from).collect(),
+            meta: pool.meta,
+            pubkey: pool.pubkey,
+            tweaked: pool.tweaked,
+            addr: pool.addr,
+            params: pool.params,
+            borrow_index: pool.borrow_index,
+        })
+    }
+}
+
 impl Storable for Pool {
     const BOUND: Bound = Bound::Unbounded;
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        let mut bytes = vec![];
-        let _ = ciborium::ser::into_writer(self, &mut bytes);
-        std::borrow::Cow::Owned(bytes)
+        std::borrow::Cow::Owned(schema::encode(self))
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        let dire = ciborium::de::from_reader(bytes.as_ref()).expect("failed to decode Pool");
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Pool");
         dire
     }
 }
@@ -106,22 +403,33 @@ impl Pool {
 
     // Validates a deposit transaction against exchange requirements
     // If valid, generates the new pool state that would result from executing the transaction
-    // Returns the new state
+    // and the depositor's position with the shares minted for the deposit
+    // Returns the new state and the updated position
     pub(crate) fn validate_deposit(
         &self,
         txid: Txid,
         nonce: u64,
+        initiator: &String,
         pool_utxo_spent: Vec<String>,
         pool_utxo_received: Vec<Utxo>,
         input_coins: Vec<InputCoin>,
         output_coins: Vec<OutputCoin>,
-    ) -> Result<(PoolState, Option<Utxo>), ExchangeError> {
+        zero_confirmed_tx_queue_length: u32,
+    ) -> Result<(PoolState, Vec<Utxo>, LpPosition), ExchangeError> {
         // Verify transaction structure (1 input coin, 0 output coins)
         (input_coins.len() == 1 && output_coins.is_empty())
             .then(|| ())
             .ok_or(ExchangeError::InvalidSignPsbtArgs(
                 "invalid input/output_coins, deposit requires 1 inputs and 0 output".to_string(),
             ))?;
+        // The shares are credited to the depositor, who must initiate the transaction
+        input_coins
+            .iter()
+            .all(|input| input.from == *initiator)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "deposit must be initiated by the depositor".to_string(),
+            ))?;
         let btc_input = input_coins[0].coin.clone();
         // Verify input coin is BTC
         (btc_input.id == CoinId::btc())
@@ -135,19 +443,17 @@ impl Pool {
         (state.nonce == nonce)
             .then(|| ())
             .ok_or(ExchangeError::PoolStateExpired(state.nonce))?;
-        // Verify previous outpoint matches the current pool UTXO
-        let pool_utxo = state.utxo.clone();
-        (pool_utxo.as_ref().map(|u| u.outpoint()).as_ref() == pool_utxo_spent.last())
+        // A deposit either adds a new UTXO to the pool without spending the existing ones,
+        // or spends all of them and merges them with the deposited BTC
+        let spent = state.spent_utxos(&pool_utxo_spent, true)?;
+        (!spent.is_empty() || state.utxos.len() < MAX_POOL_UTXOS)
             .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                "pool_utxo_spent/pool state mismatch".to_string(),
-            ))?;
-        // Verify new output exists in the transaction
-        let pool_new_outpoint = pool_utxo_received.last().map(|s| s.clone()).ok_or(
-            ExchangeError::InvalidSignPsbtArgs("pool_utxo_received not found".to_string()),
-        )?;
-        // Verify deposit amount meets minimum requirement
-        (btc_input.value >= MIN_BTC_VALUE as u128)
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                "pool holds {} UTXOs, the deposit must spend them",
+                MAX_POOL_UTXOS
+            )))?;
+        // Verify deposit amount meets the minimum required under the current congestion
+        (btc_input.value >= self.minimal_tx_value(zero_confirmed_tx_queue_length) as u128)
             .then(|| ())
             .ok_or(ExchangeError::TooSmallFunds)?;
         // Calculate the new pool state after deposit
@@ -155,38 +461,183 @@ impl Pool {
             .value
             .try_into()
             .map_err(|_| ExchangeError::Overflow)?;
-        let (btc_pool, rune_pool) = pool_utxo
-            .as_ref()
-            .map(|u| (u.sats, u.coins.value_of(&self.meta.id)))
-            .unwrap_or((0u64, 0u128));
+        // The new UTXO holds the assets of the spent UTXOs plus the deposit
+        let (btc_pool, rune_pool) = if spent.is_empty() {
+            (0u64, 0u128)
+        } else {
+            (state.btc_supply()?, state.rune_supply(self.base_id())?)
+        };
 
         let btc_output = btc_pool
             .checked_add(sats_input)
             .ok_or(ExchangeError::Overflow)?;
+        // Create new UTXO with updated balance
+        let pool_output = self.received_utxo(&pool_utxo_received, btc_output, rune_pool)?;
 
-        let mut coins = CoinBalances::new();
-        coins.add_coin(&CoinBalance {
-            value: rune_pool,
-            id: self.meta.id,
-        });
+        // Mint shares to the depositor in proportion to the pool value before the deposit
+        // Note: BTC deposited before share accounting existed is held by protocol-owned shares
+        let minted = lp::shares_for(
+            sats_input,
+            crate::get_pool_shares(&self.addr),
+            self.pool_value(),
+        )
+        .ok_or(ExchangeError::Overflow)?;
+        let mut position = crate::get_lp_position(&self.addr, initiator)
+            .unwrap_or_else(|| LpPosition::new(&self.addr, initiator));
+        let lp_state = LpState {
+            id: Some(txid),
+            shares: position
+                .shares()
+                .checked_add(minted)
+                .ok_or(ExchangeError::Overflow)?,
+        };
+        position.commit(lp_state);
+
+        // Update the state with new UTXO, increment nonce, and set transaction ID
+        if !spent.is_empty() {
+            state.utxos.clear();
+        }
+        state.utxos.push(pool_output);
+        state.nonce += 1;
+        state.id = Some(txid);
+        Ok((state, spent, position))
+    }
+
+    // Calculates how much BTC a depositor can withdraw from the pool
+    // A depositor can withdraw at most the value of their shares, and only the liquidity
+    // that is not lent out can be withdrawn; like borrowing, a withdrawal must leave
+    // at least the BTC dust amount in the pool
+    // Returns the actual BTC amount that can be withdrawn and the shares burned for it
+    pub(crate) fn available_to_withdraw(
+        &self,
+        position: &LpPosition,
+        output_btc: CoinBalance,
+    ) -> Result<(CoinBalance, u128), ExchangeError> {
+        // Verify the requested output is BTC
+        let btc_meta = CoinMeta::btc();
+        (output_btc.id == btc_meta.id)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidPool)?;
+        // Get the most recent pool state and verify it's not empty
+        let recent_state = self.states.last().ok_or(ExchangeError::EmptyPool)?;
+        let btc_supply = recent_state.btc_supply()?;
+        (btc_supply != 0)
+            .then(|| ())
+            .ok_or(ExchangeError::EmptyPool)?;
+
+        // Calculate the maximum amount that can be withdrawn
+        let expected_btc: u64 = output_btc
+            .value
+            .try_into()
+            .map_err(|_| ExchangeError::Overflow)?;
+        let min_hold = btc_meta.min_amount as u64; // Minimum BTC that must remain in the pool
+        let max_withdraw = btc_supply
+            .checked_sub(min_hold)
+            .ok_or(ExchangeError::Overflow)?;
+
+        // Calculate the value of the depositor's shares
+        let total_shares = crate::get_pool_shares(&self.addr);
+        let pool_value = self.pool_value();
+        let position_value = lp::value_of_shares(position.shares(), total_shares, pool_value)
+            .ok_or(ExchangeError::Overflow)?;
+
+        // If requested amount exceeds available liquidity or the depositor's shares, provide the maximum possible
+        let offer = expected_btc.min(max_withdraw).min(position_value);
+        let burned = lp::shares_to_burn(offer, total_shares, pool_value)
+            .ok_or(ExchangeError::Overflow)?
+            .min(position.shares());
+        Ok((
+            CoinBalance {
+                id: btc_meta.id,
+                value: offer as u128,
+            },
+            burned,
+        ))
+    }
+
+    // Validates a withdrawal transaction against exchange requirements
+    // If valid, generates the new pool state that would result from executing the transaction
+    // and the depositor's position with the shares burned for the withdrawal
+    // The BTC is only released to the depositor, who must initiate the transaction
+    // Returns the new state and the updated position
+    pub(crate) fn validate_withdraw(
+        &self,
+        txid: Txid,
+        nonce: u64,
+        initiator: &String,
+        pool_utxo_spent: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+    ) -> Result<(PoolState, Vec<Utxo>, LpPosition), ExchangeError> {
+        // Verify transaction structure (0 input coins, 1 output coin)
+        (input_coins.is_empty() && output_coins.len() == 1)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "invalid input/output coins, withdraw requires 0 input and 1 output".to_string(),
+            ))?;
+        let output = output_coins.first().clone().expect("checked;qed");
+        let (mut state, spent) = self.spend_pool_state(nonce, &pool_utxo_spent)?;
+        // The BTC is released to the depositor, whose address identifies the position
+        (output.to == *initiator)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "withdraw must be initiated by the depositor".to_string(),
+            ))?;
+        let mut position = crate::get_lp_position(&self.addr, &output.to)
+            .ok_or(ExchangeError::NoLiquidityPosition)?;
+        // Calculate how much BTC can be withdrawn and how many shares are burned
+        let (btc, burned) = self.available_to_withdraw(&position, output.coin.clone())?;
+        let output_btc: u64 = btc.value.try_into().map_err(|_| ExchangeError::Overflow)?;
+        // Verify withdrawal amount meets minimum requirement
+        (output_btc >= MIN_BTC_VALUE)
+            .then(|| ())
+            .ok_or(ExchangeError::TooSmallFunds)?;
+        // Verify the output coin matches what was calculated by available_to_withdraw
+        (output.coin == btc)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "output mismatch with pre_withdraw".to_string(),
+            ))?;
+        // Calculate the new pool balance after the withdrawal
+        let btc_output = state
+            .btc_supply()?
+            .checked_sub(output_btc)
+            .ok_or(ExchangeError::Overflow)?;
         // Create new UTXO with updated balance
-        let pool_output = Utxo::try_from(pool_new_outpoint.outpoint(), coins, btc_output)
-            .map_err(|_| ExchangeError::InvalidTxid)?;
+        let pool_output = self.received_utxo(
+            &pool_utxo_received,
+            btc_output,
+            state.rune_supply(self.base_id())?,
+        )?;
+
+        // Burn the shares of the withdrawn BTC
+        let lp_state = LpState {
+            id: Some(txid),
+            shares: position
+                .shares()
+                .checked_sub(burned)
+                .ok_or(ExchangeError::Overflow)?,
+        };
+        position.commit(lp_state);
 
         // Update the state with new UTXO, increment nonce, and set transaction ID
-        state.utxo = Some(pool_output);
+        state.utxos = vec![pool_output];
         state.nonce += 1;
         state.id = Some(txid);
-        Ok((state, pool_utxo))
+
+        Ok((state, spent, position))
     }
 
     // Calculates how much collateral (RICH) is needed to borrow the specified amount of BTC
-    // In this demo program, the collateral ratio is 1:1 (equal amounts of RICH and BTC)
+    // The collateral is valued with the oracle price, and the pool's LTV determines how much BTC
+    // can be borrowed against that value (e.g. 5000 bps requires collateral worth twice the BTC)
     // Also checks if the pool has sufficient BTC to lend the requested amount
     // Returns a tuple of (required collateral, actual BTC amount that can be borrowed)
     pub(crate) fn available_to_borrow(
         &self,
         output_btc: CoinBalance,
+        price: &Price,
     ) -> Result<(CoinBalance, CoinBalance), ExchangeError> {
         // Verify the requested output is BTC
         let btc_meta = CoinMeta::btc();
@@ -195,7 +646,7 @@ impl Pool {
             .ok_or(ExchangeError::InvalidPool)?;
         // Get the most recent pool state and verify it's not empty
         let recent_state = self.states.last().ok_or(ExchangeError::EmptyPool)?;
-        let btc_supply = recent_state.btc_supply();
+        let btc_supply = recent_state.btc_supply()?;
         (btc_supply != 0)
             .then(|| ())
             .ok_or(ExchangeError::EmptyPool)?;
@@ -214,11 +665,24 @@ impl Pool {
             expected_btc
         };
 
-        // Return the required collateral and actual BTC amount (1:1 ratio)
+        // Required collateral value = borrowed BTC / LTV, rounded up in favor of the pool
+        let ltv = self.params.ltv_bps as u128;
+        (ltv != 0)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidState("ltv is zero".to_string()))?;
+        let collateral_value = (offer as u128)
+            .checked_mul(MAX_BPS as u128)
+            .ok_or(ExchangeError::Overflow)?
+            .div_ceil(ltv);
+        let collateral = price
+            .amount_for(collateral_value)
+            .ok_or(ExchangeError::Overflow)?;
+
+        // Return the required collateral and actual BTC amount
         Ok((
             CoinBalance {
                 id: self.base_id(),
-                value: offer as u128, // RICH collateral amount equals BTC amount (1:1 ratio)
+                value: collateral, // RICH collateral amount required by the pool's LTV
             },
             CoinBalance {
                 id: btc_meta.id,
@@ -229,16 +693,21 @@ impl Pool {
 
     // Validates a borrow transaction against exchange requirements
     // If valid, generates the new pool state that would result from executing the transaction
-    // Returns the new state
+    // and the borrower's loan with the borrowed amount and locked collateral added
+    // The collateral is valued with the given price, which must not be stale
+    // Returns the new state and the updated loan
     pub(crate) fn validate_borrow(
         &self,
         txid: Txid,
         nonce: u64,
+        initiator: &String,
         pool_utxo_spent: Vec<String>,
         pool_utxo_received: Vec<Utxo>,
         input_coins: Vec<InputCoin>,
         output_coins: Vec<OutputCoin>,
-    ) -> Result<(PoolState, Utxo), ExchangeError> {
+        price: &Price,
+        zero_confirmed_tx_queue_length: u32,
+    ) -> Result<(PoolState, Vec<Utxo>, Loan), ExchangeError> {
         // Verify transaction structure (1 input coin, 1 output coin)
         (input_coins.len() == 1 && output_coins.len() == 1)
             .then(|| ())
@@ -247,6 +716,12 @@ impl Pool {
             ))?;
         let input = input_coins.first().clone().expect("checked;qed");
         let output = output_coins.first().clone().expect("checked;qed");
+        // The collateral is locked on the loan of its owner, who must initiate the transaction
+        (input.from == *initiator)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "borrow must be initiated by the collateral owner".to_string(),
+            ))?;
         // Get the current pool state
         let mut state = self
             .states
@@ -257,32 +732,22 @@ impl Pool {
         (state.nonce == nonce)
             .then(|| ())
             .ok_or(ExchangeError::PoolStateExpired(state.nonce))?;
-        // Verify previous outpoint exists and matches the current pool UTXO
-        let prev_outpoint =
-            pool_utxo_spent
-                .last()
-                .map(|s| s.clone())
-                .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                    "pool_utxo_spent not found".to_string(),
-                ))?;
-        let prev_utxo = state.utxo.clone().ok_or(ExchangeError::EmptyPool)?;
-        (prev_outpoint == prev_utxo.outpoint()).then(|| ()).ok_or(
-            ExchangeError::InvalidSignPsbtArgs("pool_utxo_spent/pool state mismatch".to_string()),
-        )?;
+        // Verify the transaction spends all the UTXOs of the pool, merging them into the new one
+        (!state.utxos.is_empty())
+            .then(|| ())
+            .ok_or(ExchangeError::EmptyPool)?;
+        let spent = state.spent_utxos(&pool_utxo_spent, false)?;
         // Calculate how much BTC can be borrowed and how much collateral is required
-        let (runes, btc) = self.available_to_borrow(output.coin)?;
+        let (runes, btc) = self.available_to_borrow(output.coin, price)?;
         let output_btc: u64 = btc.value.try_into().map_err(|_| ExchangeError::Overflow)?;
-        // Verify borrow amount meets minimum requirement
-        (output_btc >= MIN_BTC_VALUE)
+        // Verify borrow amount meets the minimum required under the current congestion
+        (output_btc >= self.minimal_tx_value(zero_confirmed_tx_queue_length))
             .then(|| ())
             .ok_or(ExchangeError::TooSmallFunds)?;
         // Calculate the new pool balances after the borrow transaction
         let (btc_output, rune_output) = (
-            prev_utxo.sats.checked_sub(output_btc),
-            prev_utxo
-                .coins
-                .value_of(&self.meta.id)
-                .checked_add(runes.value),
+            state.btc_supply()?.checked_sub(output_btc),
+            state.rune_supply(self.base_id())?.checked_add(runes.value),
         );
 
         // Verify the output and input coins match what was calculated by available_to_borrow
@@ -303,62 +768,459 @@ impl Pool {
             rune_output.ok_or(ExchangeError::Overflow)?,
         );
 
-        let mut coins = CoinBalances::new();
-        coins.add_coin(&CoinBalance {
-            value: rune_output,
-            id: self.base_id(),
-        });
         // Create new UTXO with updated balance
-        let pool_output = Utxo::try_from(
-            pool_utxo_received
-                .last()
-                .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                    "pool_utxo_received not found".to_string(),
-                ))?
-                .outpoint(),
-            coins,
-            btc_output,
-        )
-        .map_err(|_| ExchangeError::InvalidTxid)?;
+        let pool_output = self.received_utxo(&pool_utxo_received, btc_output, rune_output)?;
+
+        // Record the borrowed BTC and the locked collateral on the loan of the collateral owner
+        let mut loan = crate::get_loan(&self.addr, &input.from)
+            .unwrap_or_else(|| Loan::new(&self.addr, &input.from));
+        let loan_state = LoanState {
+            id: Some(txid),
+            collateral: loan
+                .collateral()
+                .checked_add(runes.value)
+                .ok_or(ExchangeError::Overflow)?,
+            scaled_debt: self
+                .borrow_index
+                .to_scaled(output_btc)
+                .and_then(|scaled| loan.scaled_debt().checked_add(scaled))
+                .ok_or(ExchangeError::Overflow)?,
+        };
+        loan.commit(loan_state);
+
+        // Update the state with new UTXO, increment nonce, and set transaction ID
+        state.utxos = vec![pool_output];
+        state.nonce += 1;
+        state.id = Some(txid);
+
+        Ok((state, spent, loan))
+    }
+
+    // Calculates how much collateral (RICH) is released when repaying the specified amount of BTC
+    // The debt includes the interest accrued since the loan was taken
+    // The collateral is released in proportion to the repaid share of the loan's debt
+    // Repaying the whole debt releases all of the loan's collateral
+    // Returns a tuple of (actual BTC amount to be repaid, collateral released to the borrower)
+    pub(crate) fn available_to_repay(
+        &self,
+        loan: &Loan,
+        input_btc: CoinBalance,
+    ) -> Result<(CoinBalance, CoinBalance), ExchangeError> {
+        // Verify the repaid asset is BTC
+        let btc_meta = CoinMeta::btc();
+        (input_btc.id == btc_meta.id)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidPool)?;
+        // Verify the loan has outstanding debt or collateral left after a liquidation
+        let debt = loan.debt(&self.borrow_index);
+        (debt != 0 || loan.collateral() != 0)
+            .then(|| ())
+            .ok_or(ExchangeError::NoOutstandingLoan)?;
+
+        // If requested amount exceeds the outstanding debt, only repay the debt
+        let expected_btc: u64 = input_btc
+            .value
+            .try_into()
+            .map_err(|_| ExchangeError::Overflow)?;
+        let offer = if expected_btc > debt {
+            debt
+        } else {
+            expected_btc
+        };
+        let released = if offer == debt {
+            loan.collateral()
+        } else {
+            loan.collateral()
+                .checked_mul(offer as u128)
+                .ok_or(ExchangeError::Overflow)?
+                / debt as u128
+        };
+
+        // Verify the pool holds enough collateral to release
+        let recent_state = self.states.last().ok_or(ExchangeError::EmptyPool)?;
+        (recent_state.rune_supply(self.base_id())? >= released)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidState(
+                "insufficient collateral in pool".to_string(),
+            ))?;
+
+        Ok((
+            CoinBalance {
+                id: btc_meta.id,
+                value: offer as u128, // Actual BTC amount that will be repaid
+            },
+            CoinBalance {
+                id: self.base_id(),
+                value: released, // RICH collateral released back to the borrower
+            },
+        ))
+    }
+
+    // Validates a repay transaction against exchange requirements
+    // If valid, generates the new pool state that would result from executing the transaction
+    // and the borrower's loan with the repaid debt and released collateral removed
+    // The collateral is only released to the borrower, who must initiate the transaction
+    // Returns the new state and the updated loan
+    pub(crate) fn validate_repay(
+        &self,
+        txid: Txid,
+        nonce: u64,
+        initiator: &String,
+        pool_utxo_spent: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+    ) -> Result<(PoolState, Vec<Utxo>, Loan), ExchangeError> {
+        // Verify transaction structure (1 input coin, 1 output coin)
+        (input_coins.len() == 1 && output_coins.len() == 1)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "invalid input/output coins, repay requires 1 input and 1 output".to_string(),
+            ))?;
+        let input = input_coins.first().clone().expect("checked;qed");
+        let output = output_coins.first().clone().expect("checked;qed");
+        let (state, spent) = self.spend_pool_state(nonce, &pool_utxo_spent)?;
+        // The collateral is released to its owner, whose address identifies the loan
+        (output.to == *initiator)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "repay must be initiated by the borrower".to_string(),
+            ))?;
+        let mut loan =
+            crate::get_loan(&self.addr, &output.to).ok_or(ExchangeError::NoOutstandingLoan)?;
+        // Calculate how much BTC is repaid and how much collateral is released
+        let (btc, runes) = self.available_to_repay(&loan, input.coin.clone())?;
+        let state = self.settle_loan(
+            txid,
+            state,
+            &mut loan,
+            &pool_utxo_received,
+            input,
+            output,
+            btc,
+            runes,
+            "pre_repay",
+            false,
+        )?;
+        Ok((state, spent, loan))
+    }
+
+    // Returns the current pool state, verifying the transaction is built on it and spends all its UTXOs
+    // Used by the actions that merge the pool's UTXOs into a single new one
+    // Returns the state and the UTXOs spent
+    fn spend_pool_state(
+        &self,
+        nonce: u64,
+        pool_utxo_spent: &Vec<String>,
+    ) -> Result<(PoolState, Vec<Utxo>), ExchangeError> {
+        // Get the current pool state
+        let state = self
+            .states
+            .last()
+            .cloned()
+            .ok_or(ExchangeError::EmptyPool)?;
+        // Verify nonce matches to prevent replay attacks
+        (state.nonce == nonce)
+            .then(|| ())
+            .ok_or(ExchangeError::PoolStateExpired(state.nonce))?;
+        // Verify the transaction spends all the UTXOs of the pool, merging them into the new one
+        (!state.utxos.is_empty())
+            .then(|| ())
+            .ok_or(ExchangeError::EmptyPool)?;
+        let spent = state.spent_utxos(pool_utxo_spent, false)?;
+        Ok((state, spent))
+    }
+
+    // Applies a payment of a loan's debt to the pool state, shared by repays and liquidations
+    // btc and runes are the amounts computed by the pre_* endpoint named by `offer`: the BTC paid in
+    // and the collateral paid out, which the transaction's input and output coins must match
+    // Commits the new loan state and returns the new pool state
+    fn settle_loan(
+        &self,
+        txid: Txid,
+        mut state: PoolState,
+        loan: &mut Loan,
+        pool_utxo_received: &Vec<Utxo>,
+        input: &InputCoin,
+        output: &OutputCoin,
+        btc: CoinBalance,
+        runes: CoinBalance,
+        offer: &str,
+        write_off: bool,
+    ) -> Result<PoolState, ExchangeError> {
+        let input_btc: u64 = btc.value.try_into().map_err(|_| ExchangeError::Overflow)?;
+        // Verify the paid amount meets minimum requirement (closing the remaining debt is always allowed)
+        let debt = loan.debt(&self.borrow_index);
+        (input_btc >= MIN_BTC_VALUE || input_btc == debt)
+            .then(|| ())
+            .ok_or(ExchangeError::TooSmallFunds)?;
+        // Calculate the new pool balances after the payment
+        let (btc_output, rune_output) = (
+            state.btc_supply()?.checked_add(input_btc),
+            state.rune_supply(self.base_id())?.checked_sub(runes.value),
+        );
+
+        // Verify the input and output coins match what was calculated by the offer
+        (input.coin == btc)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                "input mismatch with {}",
+                offer
+            )))?;
+        (output.coin == runes)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                "output mismatch with {}",
+                offer
+            )))?;
+
+        // Handle potential overflows
+        let (btc_output, rune_output) = (
+            btc_output.ok_or(ExchangeError::Overflow)?,
+            rune_output.ok_or(ExchangeError::Overflow)?,
+        );
+
+        // Create new UTXO with updated balance
+        let pool_output = self.received_utxo(pool_utxo_received, btc_output, rune_output)?;
+
+        // Remove the repaid debt and the collateral paid out from the loan
+        let loan_state = LoanState {
+            id: Some(txid),
+            ..loan.paid_down(input_btc, runes.value, &self.borrow_index, write_off)?
+        };
+        loan.commit(loan_state);
+
+        // Update the state with new UTXO, increment nonce, and set transaction ID
+        state.utxos = vec![pool_output];
+        state.nonce += 1;
+        state.id = Some(txid);
+        Ok(state)
+    }
+
+    // Returns true if the loan's debt exceeds its collateral value weighted by the liquidation threshold
+    pub fn is_liquidatable(&self, loan: &Loan, price: &Price) -> Result<bool, ExchangeError> {
+        let collateral_value = price
+            .value_of(loan.collateral())
+            .ok_or(ExchangeError::Overflow)?;
+        self.params
+            .is_liquidatable(loan.debt(&self.borrow_index), collateral_value)
+    }
+
+    // Calculates how much collateral (RICH) a liquidator receives for repaying the specified amount of BTC
+    // The liquidator is paid the value of the repaid debt plus the pool's liquidation bonus,
+    // valued with the oracle price and capped by the loan's collateral
+    // Returns a tuple of (actual BTC amount to be repaid, collateral seized by the liquidator)
+    pub(crate) fn available_to_liquidate(
+        &self,
+        loan: &Loan,
+        input_btc: CoinBalance,
+        price: &Price,
+    ) -> Result<(CoinBalance, CoinBalance), ExchangeError> {
+        // Verify the repaid asset is BTC
+        let btc_meta = CoinMeta::btc();
+        (input_btc.id == btc_meta.id)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidPool)?;
+        // Verify the loan is under-collateralized
+        self.is_liquidatable(loan, price)?
+            .then(|| ())
+            .ok_or(ExchangeError::LoanHealthy)?;
+
+        // If requested amount exceeds the outstanding debt, only repay the debt
+        let debt = loan.debt(&self.borrow_index);
+        let expected_btc: u64 = input_btc
+            .value
+            .try_into()
+            .map_err(|_| ExchangeError::Overflow)?;
+        let offer = if expected_btc > debt {
+            debt
+        } else {
+            expected_btc
+        };
+        // Seized collateral = repaid value * (1 + bonus), rounded down and capped by the loan's collateral
+        let seized = price
+            .amount_worth(self.params.liquidation_reward(offer)?)
+            .ok_or(ExchangeError::Overflow)?
+            .min(loan.collateral());
+        // A liquidation must seize some collateral, otherwise the liquidator pays for nothing
+        (seized != 0)
+            .then(|| ())
+            .ok_or(ExchangeError::TooSmallFunds)?;
+
+        // Verify the pool holds enough collateral to release
+        let recent_state = self.states.last().ok_or(ExchangeError::EmptyPool)?;
+        (recent_state.rune_supply(self.base_id())? >= seized)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidState(
+                "insufficient collateral in pool".to_string(),
+            ))?;
+
+        Ok((
+            CoinBalance {
+                id: btc_meta.id,
+                value: offer as u128, // Actual BTC amount that will be repaid
+            },
+            CoinBalance {
+                id: self.base_id(),
+                value: seized, // RICH collateral paid to the liquidator
+            },
+        ))
+    }
+
+    // Validates a liquidation transaction against exchange requirements
+    // The liquidator repays part of the borrower's debt in BTC and receives discounted collateral
+    // If valid, generates the new pool state that would result from executing the transaction
+    // and the borrower's loan with the repaid debt and seized collateral removed
+    // Returns the new state and the updated loan
+    pub(crate) fn validate_liquidate(
+        &self,
+        txid: Txid,
+        nonce: u64,
+        borrower: String,
+        pool_utxo_spent: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+        price: &Price,
+    ) -> Result<(PoolState, Vec<Utxo>, Loan), ExchangeError> {
+        // Verify transaction structure (1 input coin, 1 output coin)
+        (input_coins.len() == 1 && output_coins.len() == 1)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "invalid input/output coins, liquidate requires 1 input and 1 output".to_string(),
+            ))?;
+        let input = input_coins.first().clone().expect("checked;qed");
+        let output = output_coins.first().clone().expect("checked;qed");
+        let (state, spent) = self.spend_pool_state(nonce, &pool_utxo_spent)?;
+        // The liquidated loan is identified by the borrower address in the action params
+        let mut loan =
+            crate::get_loan(&self.addr, &borrower).ok_or(ExchangeError::NoOutstandingLoan)?;
+        // Calculate how much BTC is repaid and how much collateral is seized
+        let (btc, runes) = self.available_to_liquidate(&loan, input.coin.clone(), price)?;
+        // Once all its collateral is seized, the rest of the debt is written off
+        let state = self.settle_loan(
+            txid,
+            state,
+            &mut loan,
+            &pool_utxo_received,
+            input,
+            output,
+            btc,
+            runes,
+            "pre_liquidate",
+            true,
+        )?;
+        Ok((state, spent, loan))
+    }
+
+    // Validates a consolidation transaction, which merges all the UTXOs of the pool into one
+    // The pool's balances are unchanged: the transaction fee is paid by whoever submits it
+    // Returns the new state and the UTXOs merged
+    pub(crate) fn validate_consolidate(
+        &self,
+        txid: Txid,
+        nonce: u64,
+        pool_utxo_spent: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+    ) -> Result<(PoolState, Vec<Utxo>), ExchangeError> {
+        // Verify transaction structure (0 input coins, 0 output coins)
+        (input_coins.is_empty() && output_coins.is_empty())
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "invalid input/output coins, consolidate requires 0 input and 0 output".to_string(),
+            ))?;
+        // Get the current pool state
+        let mut state = self
+            .states
+            .last()
+            .cloned()
+            .ok_or(ExchangeError::EmptyPool)?;
+        // Verify nonce matches to prevent replay attacks
+        (state.nonce == nonce)
+            .then(|| ())
+            .ok_or(ExchangeError::PoolStateExpired(state.nonce))?;
+        // Verify there is something to merge and the transaction spends all the UTXOs of the pool
+        (state.utxos.len() > 1)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                "pool holds a single UTXO, nothing to consolidate".to_string(),
+            ))?;
+        let spent = state.spent_utxos(&pool_utxo_spent, false)?;
+        // Create new UTXO holding all the assets of the pool
+        let pool_output = self.received_utxo(
+            &pool_utxo_received,
+            state.btc_supply()?,
+            state.rune_supply(self.base_id())?,
+        )?;
 
         // Update the state with new UTXO, increment nonce, and set transaction ID
-        state.utxo = Some(pool_output);
+        state.utxos = vec![pool_output];
         state.nonce += 1;
         state.id = Some(txid);
 
-        Ok((state, prev_utxo))
+        Ok((state, spent))
+    }
+
+    // Builds the UTXO received by the pool from pool_utxo_received, holding the given balances
+    fn received_utxo(
+        &self,
+        pool_utxo_received: &Vec<Utxo>,
+        sats: u64,
+        runes: u128,
+    ) -> Result<Utxo, ExchangeError> {
+        received_utxo(pool_utxo_received, self.base_id(), sats, runes)
     }
 
     // Rollback the pool state to before the specified transaction
     // Removes the state created by txid and all subsequent states
-    pub(crate) fn rollback(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+    // Returns the IDs of the transactions whose states were removed
+    pub(crate) fn rollback(&mut self, txid: Txid) -> Result<Vec<Txid>, ExchangeError> {
         let idx = self
             .states
             .iter()
             .position(|state| state.id == Some(txid))
             .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
+        let removed = self.states[idx..]
+            .iter()
+            .filter_map(|state| state.id)
+            .collect();
         if idx == 0 {
             self.states.clear();
-            return Ok(());
+            return Ok(removed);
         }
         self.states.truncate(idx);
-        Ok(())
+        Ok(removed)
     }
 
     // Finalize a transaction by making its state the new base state
     // Removes all states before the specified transaction
-    pub(crate) fn finalize(&mut self, txid: Txid) -> Result<(), ExchangeError> {
+    // Returns the transactions of the removed states, which the finalized state builds on
+    pub(crate) fn finalize(&mut self, txid: Txid) -> Result<Vec<Txid>, ExchangeError> {
         let idx = self
             .states
             .iter()
             .position(|state| state.id == Some(txid))
             .ok_or(ExchangeError::InvalidState("txid not found".to_string()))?;
         if idx == 0 {
-            return Ok(());
+            return Ok(vec![]);
         }
+        let superseded = self.states[..idx]
+            .iter()
+            .filter_map(|state| state.id)
+            .collect();
         self.states.rotate_left(idx);
         self.states.truncate(self.states.len() - idx);
-        Ok(())
+        Ok(superseded)
+    }
+
+    // Accrues interest on the pool's loans up to the specified block height
+    // The rate is taken from the rate model at the current utilization
+    // Called for every block delivered by the orchestrator, so every replica accrues identically
+    pub(crate) fn accrue_interest(&mut self, height: u32, borrowed: u64) {
+        let rate = self.rates(borrowed).borrow_rate_bps;
+        self.borrow_index.accrue(rate, height);
     }
 
     // Adds a new PoolState to the chain after a transaction is executed
@@ -366,3 +1228,209 @@ impl Pool {
         self.states.push(state);
     }
 }
+
+// Builds the UTXO received by a pool holding the given balances of BTC and of its rune
+// The transaction must create exactly one pool UTXO, and the intention must claim for it the
+// balances computed from the pool state: verify_psbt checks the PSBT pays the claimed balances,
+// so the pool never records sats or runes it does not hold
+fn received_utxo(
+    pool_utxo_received: &Vec<Utxo>,
+    rune_id: CoinId,
+    sats: u64,
+    runes: u128,
+) -> Result<Utxo, ExchangeError> {
+    let claimed = match pool_utxo_received.as_slice() {
+        [claimed] => claimed,
+        _ => {
+            return Err(ExchangeError::InvalidSignPsbtArgs(
+                "pool_utxo_received must hold exactly one UTXO".to_string(),
+            ));
+        }
+    };
+    (claimed.sats == sats && claimed.coins.value_of(&rune_id) == runes)
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+            "pool_utxo_received mismatch, expected {} sats and {} runes",
+            sats, runes
+        )))?;
+    let mut coins = CoinBalances::new();
+    coins.add_coin(&CoinBalance {
+        value: runes,
+        id: rune_id,
+    });
+    Utxo::try_from(claimed.outpoint(), coins, sats).map_err(|_| ExchangeError::InvalidTxid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> PoolParams {
+        PoolParams {
+            ltv_bps: 5000,
+            liquidation_threshold_bps: 8000,
+            liquidation_bonus_bps: 500,
+            ..PoolParams::default()
+        }
+    }
+
+    #[test]
+    fn loans_are_liquidatable_above_the_threshold() {
+        let params = params();
+        // 10000 satoshis of collateral support up to 8000 satoshis of debt
+        assert!(!params.is_liquidatable(8000, 10000).unwrap());
+        assert!(params.is_liquidatable(8001, 10000).unwrap());
+        // A loan without debt is never liquidatable, even without collateral
+        assert!(!params.is_liquidatable(0, 0).unwrap());
+        assert!(params.is_liquidatable(1, 0).unwrap());
+        assert!(params.is_liquidatable(1, u128::MAX).is_err());
+    }
+
+    #[test]
+    fn liquidators_earn_the_bonus() {
+        let params = params();
+        assert_eq!(params.liquidation_reward(10000).unwrap(), 10500);
+        assert_eq!(params.liquidation_reward(19).unwrap(), 19);
+        assert_eq!(params.liquidation_reward(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn min_tx_value_grows_with_the_queue_up_to_cap() {
+        let curve = MinTxValueCurve {
+            base: 10000,
+            step_bps: 5000,
+            cap: 30000,
+        };
+        assert_eq!(curve.value_at(0), 10000);
+        assert_eq!(curve.value_at(1), 15000);
+        assert_eq!(curve.value_at(3), 25000);
+        assert_eq!(curve.value_at(4), 30000);
+        assert_eq!(curve.value_at(u32::MAX), 30000);
+        assert_eq!(MinTxValueCurve::default().value_at(100), MIN_BTC_VALUE);
+    }
+
+    #[test]
+    fn min_tx_value_of_unvalidated_curves_does_not_overflow() {
+        let curve = MinTxValueCurve {
+            base: u64::MAX,
+            step_bps: u64::MAX,
+            cap: 0,
+        };
+        assert_eq!(curve.value_at(u32::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn min_tx_value_curves_are_bounded() {
+        assert!(MinTxValueCurve::default().validate().is_ok());
+        let steep = MinTxValueCurve {
+            step_bps: MAX_MIN_TX_VALUE_STEP_BPS + 1,
+            ..MinTxValueCurve::default()
+        };
+        assert!(steep.validate().is_err());
+        let huge = MinTxValueCurve {
+            cap: MAX_MIN_TX_VALUE + 1,
+            ..MinTxValueCurve::default()
+        };
+        assert!(huge.validate().is_err());
+        let inverted = MinTxValueCurve {
+            base: 20000,
+            cap: 10000,
+            ..MinTxValueCurve::default()
+        };
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn params_must_be_consistent() {
+        assert!(params().validate().is_ok());
+        assert!(PoolParams::default().validate().is_ok());
+        let above_threshold = PoolParams {
+            ltv_bps: 9000,
+            ..params()
+        };
+        assert!(above_threshold.validate().is_err());
+        let no_ltv = PoolParams {
+            ltv_bps: 0,
+            ..params()
+        };
+        assert!(no_ltv.validate().is_err());
+        let huge_bonus = PoolParams {
+            liquidation_bonus_bps: MAX_BPS + 1,
+            ..params()
+        };
+        assert!(huge_bonus.validate().is_err());
+    }
+
+    const RUNE: CoinId = CoinId {
+        block: 840000,
+        tx: 1,
+    };
+
+    fn utxo(vout: u32, sats: u64, runes: u128) -> Utxo {
+        let mut coins = CoinBalances::new();
+        coins.add_coin(&CoinBalance {
+            id: RUNE,
+            value: runes,
+        });
+        let outpoint = format!("{}:{}", "11".repeat(32), vout);
+        Utxo::try_from(outpoint, coins, sats).unwrap()
+    }
+
+    fn state(utxos: Vec<Utxo>) -> PoolState {
+        PoolState {
+            id: None,
+            nonce: 1,
+            utxos,
+        }
+    }
+
+    #[test]
+    fn supplies_sum_all_the_pool_utxos() {
+        let state = state(vec![utxo(0, 10000, 5), utxo(1, 20000, 7)]);
+        assert_eq!(state.btc_supply().unwrap(), 30000);
+        assert_eq!(state.rune_supply(RUNE).unwrap(), 12);
+        assert_eq!(state.rune_supply(CoinId::btc()).unwrap(), 0);
+        let overflowing = PoolState {
+            utxos: vec![utxo(0, u64::MAX, u128::MAX), utxo(1, 1, 1)],
+            ..state
+        };
+        assert!(matches!(
+            overflowing.btc_supply(),
+            Err(ExchangeError::Overflow)
+        ));
+        assert!(matches!(
+            overflowing.rune_supply(RUNE),
+            Err(ExchangeError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn transactions_spend_all_the_pool_utxos_or_none() {
+        let state = state(vec![utxo(0, 10000, 0), utxo(1, 20000, 0)]);
+        let all: Vec<String> = state.utxos.iter().map(|u| u.outpoint()).collect();
+        assert_eq!(state.spent_utxos(&all, false).unwrap(), state.utxos);
+        assert_eq!(state.spent_utxos(&all, true).unwrap(), state.utxos);
+        // Only deposits may leave the pool UTXOs unspent
+        assert!(state.spent_utxos(&vec![], true).unwrap().is_empty());
+        assert!(state.spent_utxos(&vec![], false).is_err());
+        // Spending part of the UTXOs, or others, is rejected
+        assert!(state.spent_utxos(&all[..1].to_vec(), true).is_err());
+        let other = vec![all[0].clone(), utxo(2, 1, 0).outpoint()];
+        assert!(state.spent_utxos(&other, true).is_err());
+    }
+
+    #[test]
+    fn received_utxo_must_hold_the_computed_balances() {
+        let claimed = utxo(0, 30000, 12);
+        let received = received_utxo(&vec![claimed.clone()], RUNE, 30000, 12).unwrap();
+        assert_eq!(received.outpoint(), claimed.outpoint());
+        assert_eq!(received.sats, 30000);
+        assert_eq!(received.coins.value_of(&RUNE), 12);
+        // The pool output must not pay the pool less than its computed state
+        assert!(received_utxo(&vec![claimed.clone()], RUNE, 30001, 12).is_err());
+        assert!(received_utxo(&vec![claimed.clone()], RUNE, 30000, 13).is_err());
+        // Exactly one pool UTXO is received
+        assert!(received_utxo(&vec![], RUNE, 30000, 12).is_err());
+        assert!(received_utxo(&vec![claimed.clone(), utxo(1, 0, 0)], RUNE, 30000, 12).is_err());
+    }
+}
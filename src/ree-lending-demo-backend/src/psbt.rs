@@ -0,0 +1,243 @@
+use crate::ExchangeError;
+use crate::pool::{Pool, PoolState};
+use crate::runestone::{self, RuneId, Runestone};
+use ree_types::{
+    CoinId, Intention, IntentionSet, Txid,
+    bitcoin::{Address, ScriptBuf, Transaction, psbt::Psbt},
+};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// Returns the script locking the outputs paid to the pool address
+fn pool_script(pool: &Pool) -> Result<ScriptBuf, ExchangeError> {
+    Address::from_str(&pool.addr)
+        .map(|addr| addr.assume_checked().script_pubkey())
+        .map_err(|_| ExchangeError::InvalidState("invalid pool address".to_string()))
+}
+
+// Verifies that the PSBT is the transaction described by the intentions before any pool UTXO is signed:
+// - its txid is the one announced by the orchestrator
+// - it spends exactly the pool UTXOs listed in pool_utxo_spent
+// - it creates the pool UTXOs listed in pool_utxo_received, at the pool address and with the claimed sats
+// - its runestone, if any, is not a cenotaph, which would burn the runes held by the pools
+// - its runestone moves into each new pool UTXO the runes of the pool state computed for the intention
+// intentions are the ones addressed to this exchange with their pool and the validated new pool state,
+// intention_set the whole set of the transaction
+pub fn verify_psbt(
+    psbt: &Psbt,
+    txid: Txid,
+    intentions: &[(&Pool, &Intention, &PoolState)],
+    intention_set: &IntentionSet,
+) -> Result<(), ExchangeError> {
+    let tx = &psbt.unsigned_tx;
+    (tx.compute_txid().to_string() == txid.to_string())
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidTxid)?;
+
+    for (pool, intention, _) in intentions {
+        let script = pool_script(pool)?;
+        // Every input locked by the pool must be one of the UTXOs the intention spends
+        let spent: Vec<String> = tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .filter(|(_, input)| {
+                input
+                    .witness_utxo
+                    .as_ref()
+                    .is_some_and(|utxo| utxo.script_pubkey == script)
+            })
+            .map(|(txin, _)| txin.previous_output.to_string())
+            .collect();
+        let all_inputs: Vec<String> = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output.to_string())
+            .collect();
+        (intention
+            .pool_utxo_spent
+            .iter()
+            .all(|outpoint| all_inputs.contains(outpoint))
+            && spent
+                .iter()
+                .all(|outpoint| intention.pool_utxo_spent.contains(outpoint)))
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidSignPsbtArgs(
+            "psbt inputs mismatch with pool_utxo_spent".to_string(),
+        ))?;
+
+        // Every pool UTXO the intention receives must be created by this transaction
+        for utxo in intention.pool_utxo_received.iter() {
+            (utxo.txid == txid)
+                .then(|| ())
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                    "pool_utxo_received not created by the transaction".to_string(),
+                ))?;
+            let output =
+                tx.output
+                    .get(utxo.vout as usize)
+                    .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                        "pool_utxo_received output not found".to_string(),
+                    ))?;
+            (output.script_pubkey == script).then(|| ()).ok_or(
+                ExchangeError::InvalidSignPsbtArgs(
+                    "pool_utxo_received not paid to the pool address".to_string(),
+                ),
+            )?;
+            (output.value.to_sat() == utxo.sats).then(|| ()).ok_or(
+                ExchangeError::InvalidSignPsbtArgs("pool_utxo_received sats mismatch".to_string()),
+            )?;
+        }
+    }
+
+    // A cenotaph burns every rune spent by the transaction, including the pools' collateral
+    Runestone::decipher(tx)
+        .is_none_or(|runestone| !runestone.cenotaph)
+        .then(|| ())
+        .ok_or(ExchangeError::InvalidSignPsbtArgs(
+            "runestone is a cenotaph".to_string(),
+        ))?;
+    verify_rune_balances(tx, txid, intentions, intention_set)
+}
+
+fn rune_id(coin: &CoinId) -> RuneId {
+    RuneId {
+        block: coin.block,
+        tx: coin.tx,
+    }
+}
+
+// Returns the runes of our pools held by each input of the transaction
+// Only the inputs spending pool UTXOs are known, from the latest state of their pool:
+// the canister has no rune indexer, so the other inputs are left empty
+fn pool_input_runes(
+    tx: &Transaction,
+    intentions: &[(&Pool, &Intention, &PoolState)],
+) -> Vec<HashMap<RuneId, u128>> {
+    tx.input
+        .iter()
+        .map(|txin| {
+            let outpoint = txin.previous_output.to_string();
+            intentions
+                .iter()
+                .filter(|(_, intention, _)| intention.pool_utxo_spent.contains(&outpoint))
+                .filter_map(|(pool, _, _)| {
+                    pool.states
+                        .last()?
+                        .utxos
+                        .iter()
+                        .find(|utxo| utxo.outpoint() == outpoint)
+                        .map(|utxo| (rune_id(&pool.meta.id), utxo.coins.value_of(&pool.meta.id)))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Follows the runestone of the transaction to compute the runes landing in each new pool UTXO,
+// and checks they match the balances of the new pool states, which the pools record as held
+// Only the runes of our pools are followed: the runestone allocates each rune separately, so the
+// runes of other exchanges or users in a multi-exchange transaction cannot change the result
+// The runes spent from the pools are known per input from their states. The runes sent by users
+// cannot be read from the transaction without a rune indexer: they are the input_coins claimed by
+// every intention of the set, which the orchestrator checks against its rune indexer before
+// submitting the transaction. This check therefore catches a runestone sending the runes elsewhere,
+// but relies on the orchestrator to reject inflated input claims. A pool UTXO of another exchange
+// holding the same rune is not accounted for either, and makes the transaction rejected
+fn verify_rune_balances(
+    tx: &Transaction,
+    txid: Txid,
+    intentions: &[(&Pool, &Intention, &PoolState)],
+    intention_set: &IntentionSet,
+) -> Result<(), ExchangeError> {
+    let ours: HashSet<RuneId> = intentions
+        .iter()
+        .map(|(pool, _, _)| rune_id(&pool.meta.id))
+        .collect();
+    let claimed = intention_set
+        .intentions
+        .iter()
+        .flat_map(|intention| intention.input_coins.iter())
+        .filter(|input| input.coin.id != CoinId::btc())
+        .map(|input| (rune_id(&input.coin.id), input.coin.value));
+    let pool_inputs = pool_input_runes(tx, intentions)
+        .into_iter()
+        .flat_map(|runes| runes.into_iter());
+    let mut inputs: HashMap<RuneId, u128> = HashMap::new();
+    for (id, value) in pool_inputs.chain(claimed) {
+        if ours.contains(&id) {
+            let balance = inputs.entry(id).or_default();
+            *balance = balance.checked_add(value).ok_or(ExchangeError::Overflow)?;
+        }
+    }
+
+    // The UTXOs the new pool states received from this transaction, with the runes they hold
+    let expected: Vec<(u32, RuneId, u128)> = intentions
+        .iter()
+        .flat_map(|(pool, _, state)| {
+            state
+                .utxos
+                .iter()
+                .filter(|utxo| utxo.txid == txid)
+                .map(|utxo| {
+                    (
+                        utxo.vout,
+                        rune_id(&pool.meta.id),
+                        utxo.coins.value_of(&pool.meta.id),
+                    )
+                })
+        })
+        .collect();
+    verify_allocation(tx, inputs, &expected)
+}
+
+// Checks the runestone of the transaction allocates to each expected output its expected runes,
+// given the runes spent by the transaction inputs
+fn verify_allocation(
+    tx: &Transaction,
+    inputs: HashMap<RuneId, u128>,
+    expected: &[(u32, RuneId, u128)],
+) -> Result<(), ExchangeError> {
+    let runestone = Runestone::decipher(tx);
+    let allocated = runestone::allocate(tx, runestone.as_ref(), inputs);
+    for (vout, id, runes) in expected {
+        let received = allocated
+            .get(*vout as usize)
+            .and_then(|allocation| allocation.get(id))
+            .copied()
+            .unwrap_or_default();
+        (received == *runes)
+            .then(|| ())
+            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                "runestone moves {} runes to the pool UTXO, the pool state holds {}",
+                received, runes
+            )))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runestone::tests::{encode, transaction};
+
+    const RUNE: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    #[test]
+    fn pool_outputs_must_receive_the_runes_of_the_new_state() {
+        // A borrow locking 500 runes of collateral in a pool holding 1000:
+        // the pool UTXO (output 1) must hold 1500 runes after the transaction
+        let inputs = HashMap::from([(RUNE, 1500)]);
+        let expected = [(1, RUNE, 1500)];
+        // Without edicts, every rune spent goes to the first output after the runestone
+        let honest = transaction(vec![], 2);
+        assert!(verify_allocation(&honest, inputs.clone(), &expected).is_ok());
+        // An edict sending the collateral back to the borrower (output 2) leaves the pool
+        // with the old balance, even when the intention claims that balance for the pool output
+        let collateral_back = transaction(encode(&[0, 840000, 1, 500, 2]), 2);
+        assert!(verify_allocation(&collateral_back, inputs, &expected).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 // This file will be moved to the SDK in the future
+use crate::events::EventKind;
 use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
 use ree_types::exchange_interfaces::NewBlockInfo;
 use thiserror::Error;
@@ -11,8 +12,14 @@ pub(crate) enum Error {
     #[error("duplicate block detected at height {height} with hash {hash}")]
     DuplicateBlock { height: u32, hash: String },
 
+    #[error("missing blocks from height {from} to {to}")]
+    Gap { from: u32, to: u32 },
+
     #[error("unrecoverable reorg detected")]
     Unrecoverable,
+
+    #[error("block at height {height} fills a gap and cannot be linked with its neighbours")]
+    Unlinked { height: u32 },
 }
 
 pub fn get_max_recoverable_reorg_depth(network: BitcoinNetwork) -> u32 {
@@ -50,8 +57,20 @@ pub(crate) fn detect_reorg(network: BitcoinNetwork, new_block: NewBlockInfo) ->
                 return Ok(());
             } else if new_block.block_height > current_block.block_height + 1 {
                 ic_cdk::println!("New block is more than one block ahead of the current block");
-                return Err(Error::Unrecoverable);
+                return Err(Error::Gap {
+                    from: current_block.block_height + 1,
+                    to: new_block.block_height - 1,
+                });
             } else {
+                // NewBlockInfo carries no previous block hash, so a block arriving for a missing height
+                // cannot be checked against the stored blocks around it: it is refused, and the height
+                // stays recorded as missing until it is final
+                if is_missing(new_block.block_height) {
+                    ic_cdk::println!("New block at a missing height cannot be linked to the chain");
+                    return Err(Error::Unlinked {
+                        height: new_block.block_height,
+                    });
+                }
                 let reorg_depth = current_block.block_height - new_block.block_height + 1;
                 ic_cdk::println!("Detected reorg - depth: {}", reorg_depth,);
                 if reorg_depth > get_max_recoverable_reorg_depth(network) {
@@ -102,12 +121,13 @@ pub fn handle_reorg(height: u32, depth: u32) {
             }
         };
         for txid in block.confirmed_txids.iter() {
-            crate::TX_RECORDS.with_borrow_mut(|m| {
-                if let Some(record) = m.remove(&(txid.clone(), true)) {
-                    m.insert((txid.clone(), false), record);
-                    ic_cdk::println!("Unconfirm txid: {}", txid);
-                }
-            });
+            if let Some(record) = crate::write_tx_record((txid.clone(), true), None) {
+                crate::write_tx_record((txid.clone(), false), Some(record.clone()));
+                // Remember the reorged transaction until it is confirmed again or dropped
+                crate::REORGED_TXIDS.with_borrow_mut(|r| r.insert(txid.clone(), h));
+                crate::events::record_for_pools(EventKind::Reorg, *txid, &record.pools, Some(h));
+                ic_cdk::println!("Unconfirm txid: {}", txid);
+            }
         }
         crate::BLOCKS.with_borrow_mut(|m| m.remove(&h));
     }
@@ -117,3 +137,40 @@ pub fn handle_reorg(height: u32, depth: u32) {
         height - depth,
     );
 }
+
+// Records the heights from..=to as missing, so blocks arriving later for them are refused
+// rather than mistaken for a reorg
+pub fn record_gap(from: u32, to: u32) {
+    ic_cdk::println!("Recording missing blocks from height {} to {}", from, to);
+    crate::BLOCK_GAPS.with_borrow_mut(|m| m.insert(from, to));
+}
+
+// Returns the gap containing the specified height, if any
+fn find_gap(height: u32) -> Option<(u32, u32)> {
+    crate::BLOCK_GAPS.with_borrow(|m| {
+        m.range(..=height)
+            .next_back()
+            .filter(|(_, to)| height <= *to)
+    })
+}
+
+pub fn is_missing(height: u32) -> bool {
+    find_gap(height).is_some()
+}
+
+// Forgets the missing heights at or below the confirmed height
+// Blocks arriving for them could no longer be reorged out, so they are treated as unrecoverable,
+// and the transactions they confirmed are finalized along with the later states of their pools
+pub fn prune_gaps(confirmed_height: u32) {
+    crate::BLOCK_GAPS.with_borrow_mut(|m| {
+        let stale: Vec<(u32, u32)> = m.range(..=confirmed_height).collect();
+        for (from, to) in stale {
+            m.remove(&from);
+            if to > confirmed_height {
+                m.insert(confirmed_height + 1, to);
+            } else {
+                ic_cdk::println!("Forgetting missing blocks from height {} to {}", from, to);
+            }
+        }
+    });
+}
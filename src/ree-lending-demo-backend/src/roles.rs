@@ -0,0 +1,119 @@
+use crate::schema::{self, Versioned};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::{Storable, storable::Bound};
+use serde::Serialize;
+
+#[derive(Clone, Copy, CandidType, Debug, Deserialize, Eq, PartialEq, Serialize)]
+// Role grants access to a set of operator endpoints without being a canister controller
+pub enum Role {
+    Admin,        // Grants and revokes roles, creates pools and resets the block and tx records
+    RiskManager,  // Adjusts the risk parameters of the pools
+    Pauser,       // Pauses and unpauses actions in a pool or in all pools
+    OracleFeeder, // Publishes collateral prices
+}
+
+#[derive(Clone, CandidType, Debug, Default, Deserialize, Serialize)]
+// Grants lists the roles held by a principal
+pub struct Grants {
+    pub roles: Vec<Role>,
+}
+
+impl Versioned for Grants {
+    const VERSION: u32 = 1;
+}
+
+impl Storable for Grants {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(schema::encode(self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let dire = schema::decode(bytes.as_ref()).expect("failed to decode Grants");
+        dire
+    }
+}
+
+fn get_grants(principal: &Principal) -> Grants {
+    crate::ROLES
+        .with_borrow(|r| r.get(&principal.to_text()))
+        .unwrap_or_default()
+}
+
+// Returns true if the principal may act with the role
+// Controllers and admins hold every role
+pub fn has_role(principal: &Principal, role: Role) -> bool {
+    if ic_cdk::api::is_controller(principal) {
+        return true;
+    }
+    let grants = get_grants(principal);
+    grants.roles.contains(&Role::Admin) || grants.roles.contains(&role)
+}
+
+fn ensure_role(role: Role) -> Result<(), String> {
+    has_role(&ic_cdk::api::caller(), role)
+        .then(|| ())
+        .ok_or("Not authorized".to_string())
+}
+
+// Guard restricting an endpoint to the admins and controllers
+pub fn ensure_admin() -> Result<(), String> {
+    ensure_role(Role::Admin)
+}
+
+// Guard restricting an endpoint to the risk managers, admins and controllers
+pub fn ensure_risk_manager() -> Result<(), String> {
+    ensure_role(Role::RiskManager)
+}
+
+// Guard restricting an endpoint to the pausers, admins and controllers
+pub fn ensure_pauser() -> Result<(), String> {
+    ensure_role(Role::Pauser)
+}
+
+// Guard restricting an endpoint to the oracle feeders, admins and controllers
+pub fn ensure_oracle_feeder() -> Result<(), String> {
+    ensure_role(Role::OracleFeeder)
+}
+
+#[update(guard = "ensure_admin")]
+// grant_role gives a role to a principal
+fn grant_role(principal: Principal, role: Role) -> Result<(), String> {
+    (principal != Principal::anonymous())
+        .then(|| ())
+        .ok_or("cannot grant a role to the anonymous principal".to_string())?;
+    crate::ROLES.with_borrow_mut(|r| {
+        let mut grants = r.get(&principal.to_text()).unwrap_or_default();
+        if !grants.roles.contains(&role) {
+            grants.roles.push(role);
+        }
+        r.insert(principal.to_text(), grants);
+    });
+    Ok(())
+}
+
+#[update(guard = "ensure_admin")]
+// revoke_role takes a role back from a principal
+// Controllers keep every role, whatever is revoked
+fn revoke_role(principal: Principal, role: Role) -> Result<(), String> {
+    crate::ROLES.with_borrow_mut(|r| {
+        let Some(mut grants) = r.get(&principal.to_text()) else {
+            return;
+        };
+        grants.roles.retain(|granted| *granted != role);
+        if grants.roles.is_empty() {
+            r.remove(&principal.to_text());
+        } else {
+            r.insert(principal.to_text(), grants);
+        }
+    });
+    Ok(())
+}
+
+#[query]
+// get_roles returns the roles granted to a principal
+fn get_roles(principal: Principal) -> Vec<Role> {
+    get_grants(&principal).roles
+}
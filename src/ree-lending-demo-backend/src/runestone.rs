@@ -0,0 +1,558 @@
+// Minimal runestone decoder following the Runes protocol specification
+// Only the parts needed to follow the runes moved by a transaction are kept:
+// edicts, the default output (pointer), and whether the runestone is a cenotaph
+use ree_types::bitcoin::{
+    Transaction, opcodes,
+    script::{Instruction, Script},
+};
+use std::collections::{HashMap, VecDeque};
+
+/// runestones are OP_RETURN outputs whose second opcode is OP_PUSHNUM_13
+const MAGIC_NUMBER: opcodes::Opcode = opcodes::all::OP_PUSHNUM_13;
+
+// Tags of the runestone fields
+const TAG_BODY: u128 = 0;
+const TAG_DIVISIBILITY: u128 = 1;
+const TAG_FLAGS: u128 = 2;
+const TAG_SPACERS: u128 = 3;
+const TAG_RUNE: u128 = 4;
+const TAG_SYMBOL: u128 = 5;
+const TAG_PREMINE: u128 = 6;
+const TAG_CAP: u128 = 8;
+const TAG_AMOUNT: u128 = 10;
+const TAG_HEIGHT_START: u128 = 12;
+const TAG_HEIGHT_END: u128 = 14;
+const TAG_OFFSET_START: u128 = 16;
+const TAG_OFFSET_END: u128 = 18;
+const TAG_MINT: u128 = 20;
+const TAG_POINTER: u128 = 22;
+
+// Bits of the flags field
+const FLAG_ETCHING: u32 = 0;
+const FLAG_TERMS: u32 = 1;
+const FLAG_TURBO: u32 = 2;
+
+/// maximum divisibility and spacers of an etched rune
+const MAX_DIVISIBILITY: u128 = 38;
+const MAX_SPACERS: u128 = 0b00000111_11111111_11111111_11111111;
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, PartialOrd, Ord)]
+// RuneId identifies a rune by the block and transaction index of its etching
+pub struct RuneId {
+    pub block: u64,
+    pub tx: u32,
+}
+
+impl RuneId {
+    fn new(block: u64, tx: u32) -> Option<Self> {
+        (block != 0 || tx == 0).then_some(Self { block, tx })
+    }
+
+    // Applies the delta encoding used by edicts
+    fn next(self, block: u128, tx: u128) -> Option<Self> {
+        let block_delta: u64 = block.try_into().ok()?;
+        Self::new(
+            self.block.checked_add(block_delta)?,
+            if block_delta == 0 {
+                self.tx.checked_add(tx.try_into().ok()?)?
+            } else {
+                tx.try_into().ok()?
+            },
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+// Edict transfers an amount of a rune to an output
+// An output equal to the number of outputs spreads the amount over all non-OP_RETURN outputs
+pub struct Edict {
+    pub id: RuneId,
+    pub amount: u128, // 0 transfers the whole remaining balance
+    pub output: u32,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+// Runestone is the protocol message carried by a transaction
+// A cenotaph is a malformed runestone: all the runes spent by the transaction are burned
+pub struct Runestone {
+    pub edicts: Vec<Edict>,
+    pub pointer: Option<u32>, // Output receiving the runes not allocated by edicts
+    pub cenotaph: bool,
+}
+
+enum Payload {
+    Valid(Vec<u8>),
+    Invalid,
+}
+
+impl Runestone {
+    // Decodes the runestone of a transaction, if it has one
+    pub fn decipher(tx: &Transaction) -> Option<Self> {
+        let payload = match Self::payload(tx)? {
+            Payload::Valid(payload) => payload,
+            Payload::Invalid => return Some(Self::cenotaph()),
+        };
+        let Some(integers) = decode_integers(&payload) else {
+            return Some(Self::cenotaph());
+        };
+        let mut cenotaph = false;
+        let mut edicts = vec![];
+        let mut fields: HashMap<u128, VecDeque<u128>> = HashMap::new();
+
+        let mut i = 0;
+        while i < integers.len() {
+            let tag = integers[i];
+            if tag == TAG_BODY {
+                let mut id = RuneId::default();
+                for chunk in integers[i + 1..].chunks(4) {
+                    if chunk.len() != 4 {
+                        cenotaph = true;
+                        break;
+                    }
+                    let Some(next) = id.next(chunk[0], chunk[1]) else {
+                        cenotaph = true;
+                        break;
+                    };
+                    let Some(output) = u32::try_from(chunk[3])
+                        .ok()
+                        .filter(|output| *output as usize <= tx.output.len())
+                    else {
+                        cenotaph = true;
+                        break;
+                    };
+                    id = next;
+                    edicts.push(Edict {
+                        id,
+                        amount: chunk[2],
+                        output,
+                    });
+                }
+                break;
+            }
+            let Some(&value) = integers.get(i + 1) else {
+                cenotaph = true;
+                break;
+            };
+            fields.entry(tag).or_default().push_back(value);
+            i += 2;
+        }
+
+        let mut flags = take(&mut fields, TAG_FLAGS, |[flags]| Some(flags)).unwrap_or_default();
+        if take_flag(&mut flags, FLAG_ETCHING) {
+            take(&mut fields, TAG_DIVISIBILITY, |[d]| {
+                (d <= MAX_DIVISIBILITY).then_some(d)
+            });
+            let premine = take(&mut fields, TAG_PREMINE, |[premine]| Some(premine));
+            take(&mut fields, TAG_RUNE, |[rune]| Some(rune));
+            take(&mut fields, TAG_SPACERS, |[s]| {
+                (s <= MAX_SPACERS).then_some(s)
+            });
+            take(&mut fields, TAG_SYMBOL, |[symbol]| {
+                char::from_u32(symbol.try_into().ok()?)
+            });
+            let (mut cap, mut amount) = (None, None);
+            if take_flag(&mut flags, FLAG_TERMS) {
+                cap = take(&mut fields, TAG_CAP, |[cap]| Some(cap));
+                take(&mut fields, TAG_HEIGHT_START, |[h]| u64::try_from(h).ok());
+                take(&mut fields, TAG_HEIGHT_END, |[h]| u64::try_from(h).ok());
+                amount = take(&mut fields, TAG_AMOUNT, |[amount]| Some(amount));
+                take(&mut fields, TAG_OFFSET_START, |[o]| u64::try_from(o).ok());
+                take(&mut fields, TAG_OFFSET_END, |[o]| u64::try_from(o).ok());
+            }
+            take_flag(&mut flags, FLAG_TURBO);
+            // The supply of the etched rune must fit in 128 bits
+            let supply = cap
+                .unwrap_or_default()
+                .checked_mul(amount.unwrap_or_default())
+                .and_then(|minted| minted.checked_add(premine.unwrap_or_default()));
+            cenotaph |= supply.is_none();
+        }
+        take(&mut fields, TAG_MINT, |[block, tx]| {
+            RuneId::new(block.try_into().ok()?, tx.try_into().ok()?)
+        });
+        let pointer = take(&mut fields, TAG_POINTER, |[pointer]| {
+            u32::try_from(pointer)
+                .ok()
+                .filter(|pointer| (*pointer as usize) < tx.output.len())
+        });
+
+        // Unknown flags and unknown even tags make the runestone a cenotaph
+        cenotaph |= flags != 0;
+        cenotaph |= fields.keys().any(|tag| tag % 2 == 0);
+        if cenotaph {
+            return Some(Self::cenotaph());
+        }
+        Some(Self {
+            edicts,
+            pointer,
+            cenotaph: false,
+        })
+    }
+
+    fn cenotaph() -> Self {
+        Self {
+            cenotaph: true,
+            ..Default::default()
+        }
+    }
+
+    // Extracts the data pushed after the magic number of the first runestone output
+    fn payload(tx: &Transaction) -> Option<Payload> {
+        for output in tx.output.iter() {
+            let script: &Script = &output.script_pubkey;
+            let mut instructions = script.instructions();
+            if instructions.next() != Some(Ok(Instruction::Op(opcodes::all::OP_RETURN))) {
+                continue;
+            }
+            if instructions.next() != Some(Ok(Instruction::Op(MAGIC_NUMBER))) {
+                continue;
+            }
+            let mut payload = vec![];
+            for instruction in instructions {
+                match instruction {
+                    Ok(Instruction::PushBytes(push)) => payload.extend_from_slice(push.as_bytes()),
+                    _ => return Some(Payload::Invalid),
+                }
+            }
+            return Some(Payload::Valid(payload));
+        }
+        None
+    }
+}
+
+// Computes the runes received by each output of a transaction, given the runes spent by its inputs
+// Edicts are applied in order, then the unallocated runes go to the pointer output,
+// or to the first non-OP_RETURN output; runes sent to OP_RETURN outputs or by a cenotaph are burned
+// Runes minted or etched by the transaction are not accounted for
+pub fn allocate(
+    tx: &Transaction,
+    runestone: Option<&Runestone>,
+    mut unallocated: HashMap<RuneId, u128>,
+) -> Vec<HashMap<RuneId, u128>> {
+    let mut allocated: Vec<HashMap<RuneId, u128>> = vec![HashMap::new(); tx.output.len()];
+    if runestone.is_some_and(|runestone| runestone.cenotaph) {
+        return allocated;
+    }
+    let destinations: Vec<usize> = tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| !output.script_pubkey.is_op_return())
+        .map(|(vout, _)| vout)
+        .collect();
+
+    for edict in runestone.map(|r| r.edicts.as_slice()).unwrap_or_default() {
+        // Edicts of the rune etched by the transaction itself are ignored
+        let Some(balance) = unallocated.get_mut(&edict.id) else {
+            continue;
+        };
+        let mut send = |balance: &mut u128, amount: u128, vout: usize| {
+            if amount > 0 {
+                *balance -= amount;
+                *allocated[vout].entry(edict.id).or_default() += amount;
+            }
+        };
+        if edict.output as usize == tx.output.len() {
+            if destinations.is_empty() {
+                continue;
+            }
+            if edict.amount == 0 {
+                // Split the whole balance evenly, the first outputs receiving the remainder
+                let share = *balance / destinations.len() as u128;
+                let remainder = (*balance % destinations.len() as u128) as usize;
+                for (i, vout) in destinations.iter().enumerate() {
+                    send(
+                        balance,
+                        if i < remainder { share + 1 } else { share },
+                        *vout,
+                    );
+                }
+            } else {
+                for vout in destinations.iter() {
+                    let amount = edict.amount.min(*balance);
+                    send(balance, amount, *vout);
+                }
+            }
+        } else {
+            let amount = if edict.amount == 0 {
+                *balance
+            } else {
+                edict.amount.min(*balance)
+            };
+            send(balance, amount, edict.output as usize);
+        }
+    }
+
+    let default_output = runestone
+        .and_then(|runestone| runestone.pointer)
+        .map(|pointer| pointer as usize)
+        .or(destinations.first().copied());
+    if let Some(vout) = default_output {
+        for (id, balance) in unallocated {
+            if balance > 0 {
+                *allocated[vout].entry(id).or_default() += balance;
+            }
+        }
+    }
+    // Runes sent to OP_RETURN outputs are burned
+    for (vout, output) in tx.output.iter().enumerate() {
+        if output.script_pubkey.is_op_return() {
+            allocated[vout].clear();
+        }
+    }
+    allocated
+}
+
+// Removes the first N values of a field if `with` accepts them
+fn take<const N: usize, T>(
+    fields: &mut HashMap<u128, VecDeque<u128>>,
+    tag: u128,
+    with: impl Fn([u128; N]) -> Option<T>,
+) -> Option<T> {
+    let field = fields.get_mut(&tag)?;
+    let mut values = [0u128; N];
+    for (i, v) in values.iter_mut().enumerate() {
+        *v = *field.get(i)?;
+    }
+    let value = with(values)?;
+    field.drain(0..N);
+    if field.is_empty() {
+        fields.remove(&tag);
+    }
+    Some(value)
+}
+
+// Clears a flag bit, returning whether it was set
+fn take_flag(flags: &mut u128, flag: u32) -> bool {
+    let mask = 1u128 << flag;
+    let set = *flags & mask != 0;
+    *flags &= !mask;
+    set
+}
+
+// Decodes a sequence of LEB128 varints, failing on overlong, overflowing or truncated ones
+fn decode_integers(payload: &[u8]) -> Option<Vec<u128>> {
+    let mut integers = vec![];
+    let mut i = 0;
+    while i < payload.len() {
+        let (integer, length) = decode_varint(&payload[i..])?;
+        integers.push(integer);
+        i += length;
+    }
+    Some(integers)
+}
+
+fn decode_varint(buffer: &[u8]) -> Option<(u128, usize)> {
+    let mut n = 0u128;
+    for (i, &byte) in buffer.iter().enumerate() {
+        if i > 18 {
+            return None;
+        }
+        let value = u128::from(byte) & 0b0111_1111;
+        if i == 18 && value & 0b0111_1100 != 0 {
+            return None;
+        }
+        n |= value << (7 * i);
+        if byte & 0b1000_0000 == 0 {
+            return Some((n, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ree_types::bitcoin::{
+        Amount, ScriptBuf, TxOut,
+        absolute::LockTime,
+        script::{Builder, PushBytesBuf},
+        transaction::Version,
+    };
+
+    pub(crate) fn encode(integers: &[u128]) -> Vec<u8> {
+        let mut payload = vec![];
+        for &integer in integers {
+            let mut n = integer;
+            while n >> 7 > 0 {
+                payload.push((n as u8 & 0b0111_1111) | 0b1000_0000);
+                n >>= 7;
+            }
+            payload.push(n as u8);
+        }
+        payload
+    }
+
+    fn runestone_output(payload: Vec<u8>) -> TxOut {
+        TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .push_opcode(MAGIC_NUMBER)
+                .push_slice(PushBytesBuf::try_from(payload).unwrap())
+                .into_script(),
+        }
+    }
+
+    // Builds a transaction with the runestone as first output, followed by the specified number of outputs
+    pub(crate) fn transaction(payload: Vec<u8>, outputs: usize) -> Transaction {
+        let mut output = vec![runestone_output(payload)];
+        output.extend((0..outputs).map(|_| TxOut {
+            value: Amount::from_sat(546),
+            script_pubkey: ScriptBuf::new(),
+        }));
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output,
+        }
+    }
+
+    fn decipher(integers: &[u128], outputs: usize) -> Runestone {
+        Runestone::decipher(&transaction(encode(integers), outputs)).unwrap()
+    }
+
+    const RUNE: RuneId = RuneId {
+        block: 840000,
+        tx: 1,
+    };
+
+    #[test]
+    fn decodes_delta_encoded_edicts() {
+        let runestone = decipher(
+            &[TAG_POINTER, 2, TAG_BODY, 840000, 1, 500, 1, 0, 2, 700, 2],
+            2,
+        );
+        assert_eq!(
+            runestone,
+            Runestone {
+                edicts: vec![
+                    Edict {
+                        id: RUNE,
+                        amount: 500,
+                        output: 1,
+                    },
+                    Edict {
+                        id: RuneId {
+                            block: 840000,
+                            tx: 3,
+                        },
+                        amount: 700,
+                        output: 2,
+                    },
+                ],
+                pointer: Some(2),
+                cenotaph: false,
+            }
+        );
+    }
+
+    #[test]
+    fn transactions_without_runestone_are_ignored() {
+        let mut tx = transaction(vec![], 1);
+        tx.output.remove(0);
+        assert_eq!(Runestone::decipher(&tx), None);
+    }
+
+    #[test]
+    fn trailing_integers_make_a_cenotaph() {
+        // A tag without value
+        assert!(decipher(&[TAG_POINTER], 1).cenotaph);
+        // An incomplete edict
+        assert!(decipher(&[TAG_BODY, 840000, 1, 500], 1).cenotaph);
+    }
+
+    #[test]
+    fn edicts_to_missing_outputs_make_a_cenotaph() {
+        // Output 2 is the split over all outputs, output 3 does not exist
+        assert!(!decipher(&[TAG_BODY, 840000, 1, 500, 2], 1).cenotaph);
+        assert!(decipher(&[TAG_BODY, 840000, 1, 500, 3], 1).cenotaph);
+    }
+
+    #[test]
+    fn invalid_varints_make_a_cenotaph() {
+        // Overlong: a continuation byte after the 19th byte
+        let mut overlong = vec![0b1000_0000; 19];
+        overlong.push(0);
+        assert!(
+            Runestone::decipher(&transaction(overlong, 1))
+                .unwrap()
+                .cenotaph
+        );
+        // Overflowing: more than 128 bits
+        let mut overflowing = vec![0b1111_1111; 18];
+        overflowing.push(0b0000_0100);
+        assert!(
+            Runestone::decipher(&transaction(overflowing, 1))
+                .unwrap()
+                .cenotaph
+        );
+        // Truncated: the last byte has the continuation bit set
+        assert!(
+            Runestone::decipher(&transaction(vec![0b1000_0000], 1))
+                .unwrap()
+                .cenotaph
+        );
+        assert_eq!(decode_varint(&encode(&[u128::MAX])), Some((u128::MAX, 19)));
+    }
+
+    #[test]
+    fn unknown_even_tags_make_a_cenotaph() {
+        assert!(decipher(&[24, 1], 1).cenotaph);
+        // Unknown odd tags are ignored
+        assert!(!decipher(&[25, 1], 1).cenotaph);
+    }
+
+    #[test]
+    fn unknown_flags_make_a_cenotaph() {
+        assert!(decipher(&[TAG_FLAGS, 1 << 3], 1).cenotaph);
+        assert!(!decipher(&[TAG_FLAGS, 1 << FLAG_ETCHING], 1).cenotaph);
+    }
+
+    #[test]
+    fn split_edicts_spread_the_remainder_over_the_first_outputs() {
+        let tx = transaction(vec![], 3);
+        let split_all = decipher(&[TAG_BODY, 840000, 1, 0, 4], 3);
+        let allocated = allocate(&tx, Some(&split_all), HashMap::from([(RUNE, 10)]));
+        assert_eq!(allocated[0].get(&RUNE), None);
+        assert_eq!(allocated[1][&RUNE], 4);
+        assert_eq!(allocated[2][&RUNE], 3);
+        assert_eq!(allocated[3][&RUNE], 3);
+
+        let split_amount = decipher(&[TAG_BODY, 840000, 1, 4, 4], 3);
+        let allocated = allocate(&tx, Some(&split_amount), HashMap::from([(RUNE, 10)]));
+        assert_eq!(allocated[1][&RUNE], 4);
+        assert_eq!(allocated[2][&RUNE], 4);
+        assert_eq!(allocated[3][&RUNE], 2);
+    }
+
+    #[test]
+    fn unallocated_runes_go_to_the_default_output() {
+        let tx = transaction(vec![], 2);
+        let edict = decipher(&[TAG_BODY, 840000, 1, 3, 2], 2);
+        let allocated = allocate(&tx, Some(&edict), HashMap::from([(RUNE, 10)]));
+        assert_eq!(allocated[1][&RUNE], 7);
+        assert_eq!(allocated[2][&RUNE], 3);
+        // Without runestone, every rune goes to the first non-OP_RETURN output
+        let allocated = allocate(&tx, None, HashMap::from([(RUNE, 10)]));
+        assert_eq!(allocated[1][&RUNE], 10);
+    }
+
+    #[test]
+    fn runes_sent_to_op_return_are_burned() {
+        let tx = transaction(vec![], 2);
+        let pointer = decipher(&[TAG_POINTER, 0], 2);
+        assert_eq!(pointer.pointer, Some(0));
+        let allocated = allocate(&tx, Some(&pointer), HashMap::from([(RUNE, 10)]));
+        assert!(allocated.iter().all(|output| output.is_empty()));
+    }
+
+    #[test]
+    fn cenotaphs_burn_every_rune() {
+        let tx = transaction(vec![], 2);
+        let cenotaph = decipher(&[24, 1], 2);
+        let allocated = allocate(&tx, Some(&cenotaph), HashMap::from([(RUNE, 10)]));
+        assert!(allocated.iter().all(|output| output.is_empty()));
+    }
+}
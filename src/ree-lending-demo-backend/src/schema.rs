@@ -0,0 +1,168 @@
+use ic_stable_structures::{Memory, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+#[derive(Serialize)]
+// Envelope wraps every value written to stable memory with the version of its layout
+struct Envelope<'a, T> {
+    v: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+// StoredEnvelope is an Envelope read back from stable memory, before its data is decoded
+struct StoredEnvelope {
+    v: u32,
+    data: ciborium::Value,
+}
+
+// Versioned is implemented by the types stored in stable memory
+// Whenever the layout of a type changes incompatibly, its VERSION is bumped
+// and migrate learns how to convert the previous layouts
+pub trait Versioned: Serialize + DeserializeOwned {
+    const VERSION: u32;
+
+    // Converts data stored with an older layout into the current one
+    // Version 0 is the data stored before envelopes were introduced
+    // By default, the data is decoded as is, relying on #[serde(default)] for added fields
+    fn migrate(version: u32, data: ciborium::Value) -> Result<Self, String> {
+        data.deserialized()
+            .map_err(|e| format!("failed to migrate from version {}: {}", version, e))
+    }
+}
+
+// Encodes a value with the current version of its layout
+pub fn encode<T: Versioned>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![];
+    let _ = ciborium::ser::into_writer(
+        &Envelope {
+            v: T::VERSION,
+            data: value,
+        },
+        &mut bytes,
+    );
+    bytes
+}
+
+// Decodes a value, migrating it if it was stored with an older layout
+pub fn decode<T: Versioned>(bytes: &[u8]) -> Result<T, String> {
+    match ciborium::de::from_reader::<StoredEnvelope, _>(bytes) {
+        Ok(StoredEnvelope { v, data }) if v == T::VERSION => {
+            data.deserialized().map_err(|e| e.to_string())
+        }
+        Ok(StoredEnvelope { v, data }) if v < T::VERSION => T::migrate(v, data),
+        Ok(StoredEnvelope { v, .. }) => Err(format!("unsupported version {}", v)),
+        // Data written before envelopes were introduced
+        Err(_) => {
+            let data: ciborium::Value =
+                ciborium::de::from_reader(bytes).map_err(|e| e.to_string())?;
+            T::migrate(0, data)
+        }
+    }
+}
+
+// Writes every entry of the map back, so that it is stored with the current layout
+fn rewrite<K, V, M>(map: &mut StableBTreeMap<K, V, M>)
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+    M: Memory,
+{
+    let entries: Vec<(K, V)> = map.iter().collect();
+    for (key, value) in entries {
+        map.insert(key, value);
+    }
+}
+
+// Migrates the data in stable memory to the current layouts
+// Any value that cannot be decoded traps, which makes the upgrade fail and keeps the previous version running
+// The EVENTS log is append-only and cannot be rewritten, so its entries are migrated as they are read
+pub fn migrate_all() {
+    crate::LENDING_POOLS.with_borrow_mut(|m| rewrite(m));
+    crate::LOANS.with_borrow_mut(|m| rewrite(m));
+    crate::LP_POSITIONS.with_borrow_mut(|m| rewrite(m));
+    crate::POOL_TOTALS.with_borrow_mut(|m| rewrite(m));
+    crate::PRICES.with_borrow_mut(|m| rewrite(m));
+    crate::PAUSE_FLAGS.with_borrow_mut(|m| rewrite(m));
+    crate::ROLES.with_borrow_mut(|m| rewrite(m));
+    crate::CONFIG.with_borrow_mut(|c| {
+        let config = c.get().clone();
+        c.set(config).expect("failed to save config");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Sample {
+        value: u64,
+        #[serde(default)]
+        label: String,
+    }
+
+    impl Versioned for Sample {
+        const VERSION: u32 = 2;
+
+        // Version 1 stored the value as a string
+        fn migrate(version: u32, data: ciborium::Value) -> Result<Self, String> {
+            if version == 1 {
+                let value: String = data.deserialized().map_err(|e| e.to_string())?;
+                return Ok(Sample {
+                    value: value.parse().map_err(|_| "invalid value".to_string())?,
+                    label: String::new(),
+                });
+            }
+            data.deserialized()
+                .map_err(|e| format!("failed to migrate from version {}: {}", version, e))
+        }
+    }
+
+    fn raw<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let sample = Sample {
+            value: 42,
+            label: "a".to_string(),
+        };
+        assert_eq!(decode::<Sample>(&encode(&sample)).unwrap(), sample);
+    }
+
+    #[test]
+    fn values_stored_without_envelope_are_migrated_from_version_0() {
+        #[derive(Serialize)]
+        struct Legacy {
+            value: u64,
+        }
+        let decoded: Sample = decode(&raw(&Legacy { value: 7 })).unwrap();
+        assert_eq!(decoded.value, 7);
+        assert_eq!(decoded.label, "");
+    }
+
+    #[test]
+    fn older_versions_are_migrated() {
+        let bytes = raw(&Envelope {
+            v: 1,
+            data: &"9".to_string(),
+        });
+        assert_eq!(decode::<Sample>(&bytes).unwrap().value, 9);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let bytes = raw(&Envelope {
+            v: 3,
+            data: &Sample {
+                value: 1,
+                label: String::new(),
+            },
+        });
+        assert!(decode::<Sample>(&bytes).is_err());
+    }
+}
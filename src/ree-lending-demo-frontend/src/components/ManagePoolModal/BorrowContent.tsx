@@ -118,13 +118,9 @@ export function BorrowContent({
       tx.addIntention({
         poolAddress: pool.address,
         action: "borrow",
-        poolUtxos: [
-          reeUtils.formatPoolUtxo(
-            pool.address,
-            borrowOffer.pool_utxo,
-            Network.Testnet
-          ),
-        ],
+        poolUtxos: borrowOffer.pool_utxos.map((utxo) =>
+          reeUtils.formatPoolUtxo(pool.address, utxo, Network.Testnet)
+        ),
         inputCoins: [
           {
             coin: {
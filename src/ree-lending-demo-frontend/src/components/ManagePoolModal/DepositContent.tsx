@@ -116,15 +116,9 @@ export function DepositContent({
       tx.addIntention({
         poolAddress: pool.address,
         action: "deposit",
-        poolUtxos: depositOffer.pool_utxo?.length
-          ? [
-              reeUtils.formatPoolUtxo(
-                pool.address,
-                depositOffer.pool_utxo[0],
-                Network.Testnet
-              ),
-            ]
-          : [],
+        poolUtxos: depositOffer.pool_utxos.map((utxo) =>
+          reeUtils.formatPoolUtxo(pool.address, utxo, Network.Testnet)
+        ),
         inputCoins: [
           {
             coin: {
@@ -61,7 +61,7 @@ export const idlFactory = ({ IDL }: { IDL: any }) => {
     block_height: IDL.Nat32,
   });
   const BorrowOffer = IDL.Record({
-    pool_utxo: Utxo,
+    pool_utxos: IDL.Vec(Utxo),
     nonce: IDL.Nat64,
     input_runes: CoinBalance,
     output_btc: CoinBalance,
@@ -78,7 +78,7 @@ export const idlFactory = ({ IDL }: { IDL: any }) => {
   });
   const Result_3 = IDL.Variant({ Ok: BorrowOffer, Err: ExchangeError });
   const DepositOffer = IDL.Record({
-    pool_utxo: IDL.Opt(Utxo),
+    pool_utxos: IDL.Vec(Utxo),
     nonce: IDL.Nat64,
   });
   const Result_4 = IDL.Variant({ Ok: DepositOffer, Err: ExchangeError });
@@ -28,24 +28,22 @@ export type CoinBalance = {
 
 export type DepositOffer = {
   nonce: bigint;
-  pool_utxo: [
-    {
-      coins: [CoinBalance];
-      sats: bigint;
-      txid: string;
-      vout: number;
-    }
-  ];
+  pool_utxos: {
+    coins: [CoinBalance];
+    sats: bigint;
+    txid: string;
+    vout: number;
+  }[];
 };
 
 export type BorrowOffer = {
   nonce: bigint;
-  pool_utxo: {
+  pool_utxos: {
     coins: [CoinBalance];
     sats: bigint;
     txid: string;
     vout: number;
-  };
+  }[];
   input_runes: CoinBalance;
   output_btc: CoinBalance;
 };